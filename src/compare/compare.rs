@@ -1,35 +1,78 @@
-use difference::{Changeset, Difference};
+use difference::Changeset;
+use crate::compare::normalize::{normalize, Normalize};
+use crate::compare::pattern::judge_pattern;
+use crate::compare::results::{BaseStatus, CompareResult};
+use crate::compare::score::{similarity, similarity_file_stream, ScoreUnit};
+use crate::compare::stream::{compare_file_stream, compare_stream, Mismatch};
+use crate::compare::structured::{judge_structured, StructureConfig};
+use crate::compare::unordered::judge_unordered;
+use crate::error::Error;
 
+/// How the output is split before comparing. `Tokens` ignores all
+/// whitespace and `Binary` compares raw bytes without any normalization;
+/// both always use the streaming comparator. The unordered, pattern and
+/// structured modes read both files into memory.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CompareMode {
+    Lines,
+    Tokens,
+    Binary,
+    /// Lines in any order, compared as a multiset.
+    UnorderedLines,
+    /// Lines in order, tokens within each line in any order.
+    UnorderedTokens,
+    /// Lines of both sides sorted before a line diff.
+    Sorted,
+    /// Every answer line is a regular expression for the output line.
+    Regex,
+    /// Every answer line is a glob pattern for the output line.
+    Glob,
+    Json,
+    Csv,
+    Xml,
+}
+
+impl CompareMode {
+    pub fn is_unordered(&self) -> bool {
+        matches!(self, CompareMode::UnorderedLines | CompareMode::UnorderedTokens | CompareMode::Sorted)
+    }
+
+    pub fn is_pattern(&self) -> bool {
+        matches!(self, CompareMode::Regex | CompareMode::Glob)
+    }
+
+    pub fn is_structured(&self) -> bool {
+        matches!(self, CompareMode::Json | CompareMode::Csv | CompareMode::Xml)
+    }
+
+    /// Modes that need both files in memory and never produce a line diff
+    /// of the original text.
+    fn in_memory(&self) -> bool {
+        self.is_unordered() || self.is_pattern() || self.is_structured()
+    }
+}
+
+#[derive(Clone)]
 pub struct CompareConfig {
     pub ignore_end_of_text_enters: Option<i32>,
     pub ignore_end_of_line_space: bool,
     pub fast_compare: bool,
     pub force_fast_compare: usize,
+    pub mode: CompareMode,
+    pub normalize: Normalize,
+    /// A more lenient policy: output that only matches under it is PE
+    /// instead of WA.
+    pub presentation: Option<Normalize>,
+    pub structure: StructureConfig,
+    /// Also gives a similarity score, for partial credit. Lines or tokens
+    /// are matched as multisets whatever the size of the files, unlike the
+    /// diff, which `force_fast_compare` turns off.
+    pub score: Option<ScoreUnit>,
 }
 
-// pub fn fuck_crlf()
-pub fn remove_end_of_text_enters(target: &mut String, mut limit: i32) -> usize {
-    let mut bound = 0usize;
-    if limit < 0 { limit = target.len() as i32; }
-    let limit = limit as usize;
-    for i in target.chars().rev() {
-        if i == '\n' { bound += 1; } else { break; }
-        if bound >= limit { break; }
-    }
-    target.truncate(target.len() - bound);
-    target.shrink_to_fit();
-    bound
-}
-
-pub fn compare_string(mut ans: String, mut output: String, config: &CompareConfig) -> Changeset {
-    if let Some(num) = config.ignore_end_of_text_enters {
-        remove_end_of_text_enters(&mut ans, num);
-        remove_end_of_text_enters(&mut output, num);
-    }
-    if config.ignore_end_of_line_space {
-        ans = ans.replace(" \n", "\n");
-        output = output.replace(" \n", "\n");
-    }
+pub fn compare_string(ans: String, output: String, config: &CompareConfig) -> Changeset {
+    let ans = normalize(&ans, config);
+    let output = normalize(&output, config);
     if config.fast_compare ||
         ans.len() > config.force_fast_compare ||
         output.len() > config.force_fast_compare {
@@ -43,21 +86,299 @@ pub fn compare_string(mut ans: String, mut output: String, config: &CompareConfi
     Changeset::new(&ans, &output, "\n")
 }
 
-pub fn compare_file(ans_path: &String, output_path: &String, config: &CompareConfig) -> Result<Changeset, String> {
-    let mut ans = std::fs::read_to_string(ans_path);
+/// Files that would not get a line diff anyway are compared as streams
+/// instead of being read into memory.
+fn use_stream(ans_path: &str, output_path: &str, config: &CompareConfig) -> Result<bool, Error> {
+    match config.mode {
+        CompareMode::Tokens | CompareMode::Binary => return Ok(true),
+        mode if mode.in_memory() => return Ok(false),
+        _ if config.fast_compare => return Ok(true),
+        _ => (),
+    }
+    for path in [ans_path, output_path] {
+        let metadata = std::fs::metadata(path).map_err(|e| Error::compare(path, e))?;
+        if metadata.len() > config.force_fast_compare as u64 {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn read_lossy(path: &str) -> Result<String, Error> {
+    let content = std::fs::read(path).map_err(|e| Error::compare(path, e))?;
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
 
-    let mut output = std::fs::read_to_string(output_path);
-    if ans.is_err() {
-        return Err(format!(
-            "Error occur when read answer file: {} :{}",
-            &ans_path, &ans.as_ref().err().unwrap().to_string()
-        ));
+/// Reads both files as text. When either one is not valid UTF-8 the verdict
+/// comes from a byte comparison, and the changeset is only built from the
+/// lossy text for display.
+fn compare_text(ans_path: &str, output_path: &str, config: &CompareConfig) -> Result<(Changeset, Option<Mismatch>), Error> {
+    let ans = std::fs::read(ans_path).map_err(|e| Error::compare(ans_path, e))?;
+    let output = std::fs::read(output_path).map_err(|e| Error::compare(output_path, e))?;
+    let (ans, output) = match (String::from_utf8(ans), String::from_utf8(output)) {
+        (Ok(ans), Ok(output)) => return Ok((compare_string(ans, output, config), None)),
+        (ans, output) => (
+            ans.map(|x| x.into_bytes()).unwrap_or_else(|e| e.into_bytes()),
+            output.map(|x| x.into_bytes()).unwrap_or_else(|e| e.into_bytes()),
+        ),
+    };
+    let mismatch = compare_stream(&ans[..], &output[..], config).map_err(|e| Error::compare(output_path, e))?;
+    let mut changeset = compare_string(
+        String::from_utf8_lossy(&ans).into_owned(),
+        String::from_utf8_lossy(&output).into_owned(),
+        config,
+    );
+    changeset.distance = match mismatch {
+        Some(_) => changeset.distance.max(1),
+        None => 0,
+    };
+    Ok((changeset, mismatch))
+}
+
+/// Compares an output file with its answer and gives the verdict. Large
+/// files keep the position of the first difference in `info` instead of a
+/// changeset, and their score is computed without loading them.
+pub fn judge_file(ans_path: &str, output_path: &str, config: &CompareConfig) -> Result<CompareResult, Error> {
+    let mut result = judge_file_strict(ans_path, output_path, config)?;
+    if let Some(unit) = config.score {
+        result.score = Some(match result.status {
+            BaseStatus::AC => 1.0,
+            _ if use_stream(ans_path, output_path, config)? =>
+                similarity_file_stream(ans_path, output_path, config, unit)?,
+            _ => similarity(&read_lossy(ans_path)?, &read_lossy(output_path)?, config, unit),
+        });
+    }
+    if result.status != BaseStatus::WA {
+        return Ok(result);
+    }
+    if let Some(policy) = config.presentation {
+        let lenient = CompareConfig {
+            normalize: policy,
+            presentation: None,
+            ..config.clone()
+        };
+        if judge_file_strict(ans_path, output_path, &lenient)?.status == BaseStatus::AC {
+            result.status = BaseStatus::PE;
+        }
     }
-    if output.is_err() {
-        return Err(format!(
-            "Error occur when read output file: {} :{}",
-            &output_path, &output.as_ref().err().unwrap().to_string()
-        ));
+    Ok(result)
+}
+
+fn judge_file_strict(ans_path: &str, output_path: &str, config: &CompareConfig) -> Result<CompareResult, Error> {
+    if use_stream(ans_path, output_path, config)? {
+        let mismatch = compare_file_stream(ans_path, output_path, config)?;
+        return Ok(CompareResult {
+            status: if mismatch.is_some() { BaseStatus::WA } else { BaseStatus::AC },
+            changeset: None,
+            info: mismatch.map(|x| x.to_string()),
+            score: None,
+        });
+    }
+    if config.mode.is_unordered() {
+        return Ok(judge_unordered(&read_lossy(ans_path)?, &read_lossy(output_path)?, config));
+    }
+    if config.mode.is_pattern() {
+        return judge_pattern(&read_lossy(ans_path)?, &read_lossy(output_path)?, config)
+            .map_err(|e| Error::config(ans_path, e));
+    }
+    if config.mode.is_structured() {
+        return judge_structured(&read_lossy(ans_path)?, &read_lossy(output_path)?, config)
+            .map_err(|e| Error::config(ans_path, e));
+    }
+    let (changeset, mismatch) = compare_text(ans_path, output_path, config)?;
+    Ok(CompareResult {
+        status: if changeset.distance != 0 { BaseStatus::WA } else { BaseStatus::AC },
+        changeset: Some(changeset),
+        info: mismatch.map(|x| x.to_string()),
+        score: None,
+    })
+}
+
+/// Lists the answer files of a test case: `path` itself, or every file in it
+/// when it is a directory, sorted by name.
+pub fn answer_files(path: &str) -> Result<Vec<String>, Error> {
+    let metadata = std::fs::metadata(path).map_err(|e| Error::io(path, e))?;
+    if !metadata.is_dir() {
+        return Ok(vec![path.to_string()]);
     }
-    Ok(compare_string(ans.unwrap(), output.unwrap(), config))
-}
\ No newline at end of file
+    let mut result = vec![];
+    for entry in std::fs::read_dir(path).map_err(|e| Error::io(path, e))? {
+        let entry = entry.map_err(|e| Error::io(path, e))?;
+        if entry.file_type().map(|x| x.is_file()).unwrap_or(false) {
+            result.push(entry.path().to_string_lossy().to_string());
+        }
+    }
+    if result.is_empty() {
+        return Err(Error::config(path, "no answer files"));
+    }
+    result.sort();
+    Ok(result)
+}
+
+/// PE is closer than WA, then the smaller diff and then the higher score
+/// win. The answer path breaks the remaining ties, so the closest answer
+/// does not depend on the order of the answers.
+fn is_closer(x: (&String, &CompareResult), y: (&String, &CompareResult)) -> bool {
+    let rank = |result: &CompareResult| (
+        result.status != BaseStatus::PE,
+        result.changeset.as_ref().map(|x| x.distance).unwrap_or(i32::MAX),
+    );
+    let score = |result: &CompareResult| result.score.unwrap_or(0.0);
+    rank(x.1).cmp(&rank(y.1))
+        .then(score(y.1).total_cmp(&score(x.1)))
+        .then(x.0.cmp(y.0))
+        .is_lt()
+}
+
+/// Accepts the output if it matches any of the answers. `info` names the
+/// answer that matched, or the closest one together with its difference.
+pub fn judge_any(ans_paths: &[String], output_path: &str, config: &CompareConfig) -> Result<CompareResult, Error> {
+    let mut closest: Option<(&String, CompareResult)> = None;
+    for path in ans_paths {
+        let result = judge_file(path, output_path, config)?;
+        if result.status == BaseStatus::AC {
+            return Ok(CompareResult {
+                info: Some(format!("matched answer {}", path)),
+                ..result
+            });
+        }
+        if closest.as_ref().map(|(p, x)| is_closer((path, &result), (p, x))).unwrap_or(true) {
+            closest = Some((path, result));
+        }
+    }
+    let (path, mut result) = closest.ok_or_else(|| Error::config(output_path, "no answer files to compare with"))?;
+    result.info = Some(match result.info {
+        Some(info) => format!("closest answer {}: {}", path, info),
+        None => format!("closest answer {}", path),
+    });
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir_utils::guard::DirGuard;
+    use crate::dir_utils::local::Local;
+    use crate::testing;
+
+    /// Writes `files` into a fresh scratch directory and returns it.
+    fn scratch(name: &str, files: &[(&str, &[u8])]) -> DirGuard<Local> {
+        let dir = testing::scratch(name);
+        for (name, data) in files {
+            std::fs::write(dir.src.join(name), data).unwrap();
+        }
+        dir
+    }
+
+    fn file(path: &std::path::Path, name: &str) -> String {
+        path.join(name).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn lenient_match_is_a_presentation_error() {
+        let tmp = scratch("pe", &[("ans", b"1 2\n\n3\n"), ("spaces", b"1  2\n\n3\n"), ("wrong", b"1 2\n\n4\n")]);
+        let path = &tmp.src;
+        let config = CompareConfig {
+            presentation: Some(Normalize { trailing_whitespace: true, collapse_blank_lines: true, ..Normalize::default() }),
+            ..CompareConfig::default()
+        };
+        assert_eq!(judge_file(&file(path, "ans"), &file(path, "ans"), &config).unwrap().status, BaseStatus::AC);
+        assert_eq!(judge_file(&file(path, "ans"), &file(path, "wrong"), &config).unwrap().status, BaseStatus::WA);
+        assert_eq!(judge_file(&file(path, "ans"), &file(path, "spaces"), &config).unwrap().status, BaseStatus::WA);
+        std::fs::write(path.join("spaces"), b"1 2\t \n\n\n3\n").unwrap();
+        assert_eq!(judge_file(&file(path, "ans"), &file(path, "spaces"), &config).unwrap().status, BaseStatus::PE);
+        let config = CompareConfig { presentation: None, ..config };
+        assert_eq!(judge_file(&file(path, "ans"), &file(path, "spaces"), &config).unwrap().status, BaseStatus::WA);
+    }
+
+    #[test]
+    fn large_files_keep_the_mismatch_in_info() {
+        let tmp = scratch("large", &[("ans", b"1\n2\n3\n"), ("output", b"1\n2\n4\n")]);
+        let path = &tmp.src;
+        let config = CompareConfig { force_fast_compare: 2, ..CompareConfig::default() };
+        let result = judge_file(&file(path, "ans"), &file(path, "output"), &config).unwrap();
+        assert_eq!(result.status, BaseStatus::WA);
+        assert!(result.changeset.is_none());
+        assert_eq!(result.info.as_deref(), Some("line 3, byte 4: expected \"3\", found \"4\""));
+    }
+
+    #[test]
+    fn invalid_utf8_is_judged_by_bytes() {
+        let tmp = scratch("utf8", &[("ans", b"\xff\n1\n"), ("same", b"\xff\r\n1"), ("other", b"\xfe\n1\n")]);
+        let path = &tmp.src;
+        let config = CompareConfig::default();
+        let result = judge_file(&file(path, "ans"), &file(path, "same"), &config).unwrap();
+        assert_eq!((result.status, result.info), (BaseStatus::AC, None));
+        let result = judge_file(&file(path, "ans"), &file(path, "other"), &config).unwrap();
+        assert_eq!(result.status, BaseStatus::WA);
+        assert_eq!(result.info.as_deref(), Some("line 1, byte 0: expected hex [ff], found hex [fe]"));
+        assert!(result.changeset.is_some());
+    }
+
+    #[test]
+    fn compare_modes_parse_by_name() {
+        assert_eq!("binary".parse::<CompareMode>().unwrap(), CompareMode::Binary);
+        assert_eq!("unordered_tokens".parse::<CompareMode>().unwrap(), CompareMode::UnorderedTokens);
+        assert_eq!("json".parse::<CompareMode>().unwrap(), CompareMode::Json);
+        assert!("Lines".parse::<CompareMode>().is_err());
+        assert!("bytes".parse::<CompareMode>().is_err());
+    }
+
+    fn result(status: BaseStatus, distance: Option<i32>, score: Option<f64>) -> CompareResult {
+        CompareResult {
+            status,
+            changeset: distance.map(|distance| Changeset { diffs: vec![], split: "\n".to_string(), distance }),
+            info: None,
+            score,
+        }
+    }
+
+    #[test]
+    fn closest_answer_ranking() {
+        let (a, b) = ("a".to_string(), "b".to_string());
+        let wa = |distance, score| result(BaseStatus::WA, distance, score);
+        assert!(is_closer((&b, &result(BaseStatus::PE, Some(9), None)), (&a, &wa(Some(1), None))));
+        assert!(is_closer((&b, &wa(Some(1), None)), (&a, &wa(Some(2), None))));
+        assert!(is_closer((&b, &wa(Some(1), None)), (&a, &wa(None, None))));
+        assert!(is_closer((&b, &wa(Some(1), Some(0.5))), (&a, &wa(Some(1), Some(0.25)))));
+        assert!(is_closer((&a, &wa(Some(1), None)), (&b, &wa(Some(1), None))));
+        assert!(!is_closer((&b, &wa(Some(1), None)), (&a, &wa(Some(1), None))));
+    }
+
+    #[test]
+    fn any_matching_answer_is_accepted() {
+        let tmp = scratch("any", &[("1", b"yes\n"), ("2", b"YES\n"), ("output", b"YES\n")]);
+        let path = &tmp.src;
+        let answers = answer_files(&path.to_string_lossy()).unwrap()
+            .into_iter().filter(|x| !x.ends_with("output")).collect::<Vec<_>>();
+        assert_eq!(answers, [file(path, "1"), file(path, "2")]);
+        let result = judge_any(&answers, &file(path, "output"), &CompareConfig::default()).unwrap();
+        assert_eq!(result.status, BaseStatus::AC);
+        assert_eq!(result.info, Some(format!("matched answer {}", file(path, "2"))));
+    }
+
+    #[test]
+    fn closest_answer_does_not_depend_on_the_order() {
+        let tmp = scratch("closest", &[("1", b"a\nb\n"), ("2", b"a\nc\n"), ("3", b"x\ny\nz\n"), ("output", b"a\nd\n")]);
+        let path = &tmp.src;
+        let mut answers = vec![file(path, "3"), file(path, "2"), file(path, "1")];
+        let config = CompareConfig::default();
+        let result = judge_any(&answers, &file(path, "output"), &config).unwrap();
+        assert_eq!(result.status, BaseStatus::WA);
+        assert_eq!(result.info, Some(format!("closest answer {}", file(path, "1"))));
+        answers.reverse();
+        let result = judge_any(&answers, &file(path, "output"), &config).unwrap();
+        assert_eq!(result.info, Some(format!("closest answer {}", file(path, "1"))));
+        assert!(judge_any(&[], &file(path, "output"), &config).is_err());
+    }
+
+    #[test]
+    fn answer_files_of_a_file_or_directory() {
+        let tmp = scratch("answers", &[("1.txt", b"")]);
+        let path = &tmp.src;
+        assert_eq!(answer_files(&file(path, "1.txt")).unwrap(), [file(path, "1.txt")]);
+        assert!(matches!(answer_files(&file(path, "2.txt")), Err(Error::NotFound { .. })));
+        std::fs::create_dir_all(path.join("empty/sub")).unwrap();
+        assert!(answer_files(&file(path, "empty")).is_err());
+    }
+}
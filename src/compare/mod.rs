@@ -1,3 +1,10 @@
 pub mod compare;
 pub mod traits;
-pub mod results;
\ No newline at end of file
+pub mod results;
+pub mod render;
+pub mod stream;
+pub mod normalize;
+pub mod unordered;
+pub mod pattern;
+pub mod structured;
+pub mod score;
\ No newline at end of file
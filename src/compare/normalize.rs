@@ -0,0 +1,275 @@
+use unicode_normalization::UnicodeNormalization;
+use crate::compare::compare::CompareConfig;
+
+pub const BOM: &[u8] = b"\xef\xbb\xbf";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UnicodeForm {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+/// Rules applied to both the answer and the output before comparing.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Normalize {
+    /// Treats `\r\n` and a lone `\r` as line endings.
+    pub line_endings: bool,
+    /// Strips all trailing whitespace (spaces, tabs, `\r`) of every line.
+    pub trailing_whitespace: bool,
+    /// Turns every run of blank lines into a single blank line.
+    pub collapse_blank_lines: bool,
+    pub leading_blank_lines: bool,
+    pub trailing_blank_lines: bool,
+    /// Strips a UTF-8 byte order mark at the start of the text.
+    pub bom: bool,
+    /// Compares lowercased text.
+    pub ignore_case: bool,
+    pub unicode: Option<UnicodeForm>,
+    /// Maps full-width ASCII variants and the ideographic space to ASCII.
+    pub width: bool,
+}
+
+/// Number of trailing newlines that `ignore_end_of_text_enters` allows to
+/// drop; a negative value drops all of them.
+pub fn enters_limit(config: &CompareConfig) -> usize {
+    match config.ignore_end_of_text_enters {
+        Some(x) if x >= 0 => x as usize,
+        Some(_) => usize::MAX,
+        None => 0,
+    }
+}
+
+fn fold_width(c: char) -> char {
+    match c as u32 {
+        0x3000 => ' ',
+        x @ 0xff01..=0xff5e => char::from_u32(x - 0xfee0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+/// Applies the Unicode form, width and case folding of `policy` to a line or
+/// a token. Text that is not valid UTF-8 is left as it is.
+pub fn fold(data: &mut Vec<u8>, policy: &Normalize) {
+    if policy.unicode.is_none() && !policy.width && !policy.ignore_case {
+        return;
+    }
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut text = match policy.unicode {
+        Some(UnicodeForm::Nfc) => text.nfc().collect::<String>(),
+        Some(UnicodeForm::Nfd) => text.nfd().collect::<String>(),
+        Some(UnicodeForm::Nfkc) => text.nfkc().collect::<String>(),
+        Some(UnicodeForm::Nfkd) => text.nfkd().collect::<String>(),
+        None => text.to_string(),
+    };
+    if policy.width {
+        text = text.chars().map(fold_width).collect();
+    }
+    if policy.ignore_case {
+        text = text.to_lowercase();
+    }
+    *data = text.into_bytes();
+}
+
+/// Normalizes a single line, without its line ending. `ended` tells whether
+/// a line ending followed it.
+pub fn normalize_line(data: &mut Vec<u8>, first: bool, ended: bool, config: &CompareConfig) {
+    let policy = &config.normalize;
+    if first && policy.bom && data.starts_with(BOM) {
+        data.drain(..BOM.len());
+    }
+    fold(data, policy);
+    if policy.trailing_whitespace {
+        while data.last().map(|x| x.is_ascii_whitespace()).unwrap_or(false) {
+            data.pop();
+        }
+    } else if config.ignore_end_of_line_space && ended && data.last() == Some(&b' ') {
+        data.pop();
+    }
+}
+
+/// Number of blank lines kept from a run of `count` blank lines. `first`
+/// and `last` tell whether the run starts or ends the text.
+pub fn blank_run(count: usize, first: bool, last: bool, config: &CompareConfig) -> usize {
+    let policy = &config.normalize;
+    let mut result = count;
+    if last {
+        let newlines = if first { count.saturating_sub(1) } else { count };
+        result -= enters_limit(config).min(newlines);
+        if policy.trailing_blank_lines {
+            result = 0;
+        }
+    }
+    if first && policy.leading_blank_lines {
+        result = 0;
+    }
+    if policy.collapse_blank_lines {
+        result = result.min(1);
+    }
+    // An empty text still has one (empty) line.
+    if first && last {
+        result = result.max(1);
+    }
+    result
+}
+
+/// Splits at line endings like `str::split`, returning every line and
+/// whether a line ending followed it.
+fn split_lines(text: &[u8], line_endings: bool) -> Vec<(&[u8], bool)> {
+    let mut result = vec![];
+    let (mut start, mut index) = (0, 0);
+    while index < text.len() {
+        let c = text[index];
+        if c == b'\n' || (line_endings && c == b'\r') {
+            result.push((&text[start..index], true));
+            if c == b'\r' && text.get(index + 1) == Some(&b'\n') {
+                index += 1;
+            }
+            start = index + 1;
+        }
+        index += 1;
+    }
+    result.push((&text[start..], false));
+    result
+}
+
+/// Applies `config.normalize` together with `ignore_end_of_text_enters` and
+/// `ignore_end_of_line_space`. Lines are joined with `\n` afterwards.
+pub fn normalize(text: &str, config: &CompareConfig) -> String {
+    let lines = split_lines(text.as_bytes(), config.normalize.line_endings)
+        .into_iter().enumerate()
+        .map(|(index, (line, ended))| {
+            let mut line = line.to_vec();
+            normalize_line(&mut line, index == 0, ended, config);
+            line
+        })
+        .collect::<Vec<Vec<u8>>>();
+    let mut result: Vec<&[u8]> = vec![];
+    let mut index = 0;
+    while index < lines.len() {
+        if !lines[index].is_empty() {
+            result.push(&lines[index]);
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while index < lines.len() && lines[index].is_empty() {
+            index += 1;
+        }
+        let count = blank_run(index - start, start == 0, index == lines.len(), config);
+        result.extend(std::iter::repeat_n(&b""[..], count));
+    }
+    String::from_utf8_lossy(&result.join(&b'\n')).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(normalize: Normalize) -> CompareConfig {
+        CompareConfig { normalize, ..CompareConfig::default() }
+    }
+
+    #[test]
+    fn line_endings_become_newlines() {
+        let config = CompareConfig::default();
+        assert_eq!(normalize("a\r\nb\rc\n", &config), "a\nb\nc");
+        assert_eq!(normalize("a\r\r\nb", &config), "a\n\nb");
+        let config = CompareConfig { normalize: Normalize::default(), ..config };
+        assert_eq!(normalize("a\r\nb", &config), "a\r\nb");
+    }
+
+    #[test]
+    fn bom_is_only_stripped_at_the_start() {
+        let config = CompareConfig::default();
+        assert_eq!(normalize("\u{feff}a\n\u{feff}b", &config), "a\n\u{feff}b");
+        assert_eq!(normalize("\u{feff}a", &self::config(Normalize::default())), "\u{feff}a");
+    }
+
+    #[test]
+    fn trailing_spaces() {
+        let config = CompareConfig::default();
+        assert_eq!(normalize("a \nb  \nc ", &config), "a\nb \nc ");
+        let config = self::config(Normalize { trailing_whitespace: true, ..Normalize::default() });
+        assert_eq!(normalize("a \t\nb  \nc ", &config), "a\nb\nc");
+    }
+
+    #[test]
+    fn trailing_enters_follow_the_limit() {
+        let mut config = CompareConfig::default();
+        assert_eq!(normalize("a\n", &config), normalize("a", &config));
+        assert_ne!(normalize("a\n\n", &config), normalize("a", &config));
+        config.ignore_end_of_text_enters = Some(-1);
+        assert_eq!(normalize("a\n\n\n", &config), "a");
+        config.ignore_end_of_text_enters = None;
+        assert_eq!(normalize("a\n", &config), "a\n");
+        assert_eq!(enters_limit(&config), 0);
+    }
+
+    #[test]
+    fn blank_line_rules() {
+        let config = self::config(Normalize { collapse_blank_lines: true, ..Normalize::default() });
+        assert_eq!(normalize("a\n\n\n\nb", &config), "a\n\nb");
+        let config = self::config(Normalize { leading_blank_lines: true, ..Normalize::default() });
+        assert_eq!(normalize("\n\na\n\nb", &config), "a\n\nb");
+        let config = self::config(Normalize { trailing_blank_lines: true, ..Normalize::default() });
+        assert_eq!(normalize("a\n\nb\n\n\n", &config), "a\n\nb");
+    }
+
+    #[test]
+    fn empty_text_keeps_one_line() {
+        let config = self::config(Normalize {
+            collapse_blank_lines: true,
+            leading_blank_lines: true,
+            trailing_blank_lines: true,
+            ..Normalize::default()
+        });
+        assert_eq!(normalize("", &config), "");
+        assert_eq!(normalize("\n\n", &config), "");
+        assert_eq!(blank_run(0, true, true, &config), 1);
+        assert_eq!(blank_run(3, false, false, &config), 1);
+    }
+
+    fn folded(text: &[u8], policy: Normalize) -> Vec<u8> {
+        let mut data = text.to_vec();
+        fold(&mut data, &policy);
+        data
+    }
+
+    #[test]
+    fn fold_case_and_width() {
+        assert_eq!(folded("ÀbC".as_bytes(), Normalize { ignore_case: true, ..Normalize::default() }), "àbc".as_bytes());
+        assert_eq!(folded("ＡＢ１\u{3000}！".as_bytes(), Normalize { width: true, ..Normalize::default() }), b"AB1 !");
+        assert_eq!(folded("ｱ".as_bytes(), Normalize { width: true, ..Normalize::default() }), "ｱ".as_bytes());
+        assert_eq!(folded("ＡＢ".as_bytes(), Normalize { width: true, ignore_case: true, ..Normalize::default() }), b"ab");
+    }
+
+    #[test]
+    fn fold_unicode_forms() {
+        let composed = "é";
+        let decomposed = "e\u{301}";
+        let form = |unicode| Normalize { unicode: Some(unicode), ..Normalize::default() };
+        assert_eq!(folded(decomposed.as_bytes(), form(UnicodeForm::Nfc)), composed.as_bytes());
+        assert_eq!(folded(composed.as_bytes(), form(UnicodeForm::Nfd)), decomposed.as_bytes());
+        assert_eq!(folded("ﬁ²".as_bytes(), form(UnicodeForm::Nfkc)), b"fi2");
+        assert_eq!(folded("ﬁ".as_bytes(), form(UnicodeForm::Nfc)), "ﬁ".as_bytes());
+        assert_eq!(folded("ﬁé".as_bytes(), form(UnicodeForm::Nfkd)), "fie\u{301}".as_bytes());
+    }
+
+    #[test]
+    fn fold_leaves_invalid_utf8_alone() {
+        let policy = Normalize { ignore_case: true, width: true, unicode: Some(UnicodeForm::Nfkc), ..Normalize::default() };
+        assert_eq!(folded(b"AB\xff", policy), b"AB\xff");
+        assert_eq!(folded(b"AB", Normalize::default()), b"AB");
+    }
+
+    #[test]
+    fn unicode_forms_parse_by_name() {
+        assert_eq!("nfkc".parse::<UnicodeForm>().unwrap(), UnicodeForm::Nfkc);
+        assert_eq!("nfd".parse::<UnicodeForm>().unwrap(), UnicodeForm::Nfd);
+        assert!("NFC".parse::<UnicodeForm>().is_err());
+    }
+}
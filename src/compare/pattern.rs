@@ -0,0 +1,150 @@
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+use crate::compare::compare::{CompareConfig, CompareMode};
+use crate::compare::normalize::{normalize, Normalize};
+use crate::compare::results::{BaseStatus, CompareResult};
+
+enum LinePattern {
+    Regex(Regex),
+    Glob(Pattern, MatchOptions),
+}
+
+impl LinePattern {
+    fn new(line: &str, mode: CompareMode, ignore_case: bool) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("invalid pattern {:?}: {}", line, e);
+        match mode {
+            CompareMode::Glob => {
+                let options = MatchOptions { case_sensitive: !ignore_case, ..MatchOptions::default() };
+                Pattern::new(line).map(|x| LinePattern::Glob(x, options)).map_err(|e| error(&e))
+            },
+            _ => RegexBuilder::new(&format!("^(?:{})$", line))
+                .case_insensitive(ignore_case)
+                .build()
+                .map(LinePattern::Regex)
+                .map_err(|e| error(&e)),
+        }
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        match self {
+            LinePattern::Regex(x) => x.is_match(line),
+            LinePattern::Glob(x, options) => x.matches_with(line, *options),
+        }
+    }
+}
+
+/// Matches every output line against the pattern on the same line of the
+/// answer. A regular expression has to match the whole line. Both sides are
+/// normalized first, but only the output is folded: folding a pattern would
+/// change its meaning (`\D` is not `\d`), so `ignore_case` makes the match
+/// case-insensitive instead. An answer with an invalid pattern is an error
+/// of the problem, not of the output.
+pub fn judge_pattern(patterns: &str, output: &str, config: &CompareConfig) -> Result<CompareResult, String> {
+    let literal = CompareConfig {
+        normalize: Normalize {
+            ignore_case: false,
+            width: false,
+            unicode: None,
+            ..config.normalize
+        },
+        ..config.clone()
+    };
+    let patterns = normalize(patterns, &literal);
+    let output = normalize(output, config);
+    let patterns = patterns.split('\n').collect::<Vec<&str>>();
+    let lines = output.split('\n').collect::<Vec<&str>>();
+    let mut info = None;
+    for (index, pattern) in patterns.iter().enumerate() {
+        let compiled = LinePattern::new(pattern, config.mode, config.normalize.ignore_case)?;
+        match lines.get(index) {
+            Some(line) if compiled.matches(line) => continue,
+            Some(line) => info = Some(format!(
+                "line {}: {:?} does not match pattern {:?}", index + 1, line, pattern
+            )),
+            None => info = Some(format!(
+                "line {}: end of file does not match pattern {:?}", index + 1, pattern
+            )),
+        }
+        break;
+    }
+    if info.is_none() && lines.len() > patterns.len() {
+        info = Some(format!(
+            "line {}: expected end of file, found {:?}", patterns.len() + 1, lines[patterns.len()]
+        ));
+    }
+    Ok(CompareResult {
+        status: if info.is_some() { BaseStatus::WA } else { BaseStatus::AC },
+        changeset: None,
+        info,
+        score: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regex() -> CompareConfig {
+        CompareConfig { mode: CompareMode::Regex, ..CompareConfig::default() }
+    }
+
+    fn glob() -> CompareConfig {
+        CompareConfig { mode: CompareMode::Glob, ..CompareConfig::default() }
+    }
+
+    #[test]
+    fn regex_has_to_match_the_whole_line() {
+        let config = regex();
+        assert_eq!(judge_pattern("\\d+\n[a-z]+|ok\n", "42\nok\n", &config).unwrap().status, BaseStatus::AC);
+        let result = judge_pattern("\\d+\n", "42x\n", &config).unwrap();
+        assert_eq!(result.status, BaseStatus::WA);
+        assert_eq!(result.info.as_deref(), Some("line 1: \"42x\" does not match pattern \"\\\\d+\""));
+        assert_eq!(judge_pattern("a|b", "ab", &config).unwrap().status, BaseStatus::WA);
+    }
+
+    #[test]
+    fn glob_matches_line_by_line() {
+        let config = glob();
+        assert_eq!(judge_pattern("case #*\n?.??\n", "case #12\n3.14\n", &config).unwrap().status, BaseStatus::AC);
+        assert_eq!(judge_pattern("[0-9]\n", "a\n", &config).unwrap().status, BaseStatus::WA);
+    }
+
+    #[test]
+    fn missing_and_extra_lines_are_wrong() {
+        let result = judge_pattern("1\n2\n", "1\n", &regex()).unwrap();
+        assert_eq!(result.info.as_deref(), Some("line 2: end of file does not match pattern \"2\""));
+        let result = judge_pattern("1\n", "1\n2\n", &regex()).unwrap();
+        assert_eq!(result.info.as_deref(), Some("line 2: expected end of file, found \"2\""));
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors() {
+        let error = judge_pattern("(\n", "(\n", &regex()).err().unwrap();
+        assert!(error.starts_with("invalid pattern \"(\""));
+        assert!(judge_pattern("[a\n", "[a\n", &glob()).is_err());
+    }
+
+    #[test]
+    fn ignore_case_does_not_fold_the_pattern() {
+        let config = CompareConfig {
+            normalize: Normalize { ignore_case: true, ..CompareConfig::default().normalize },
+            ..regex()
+        };
+        assert_eq!(judge_pattern("\\D+\n", "ABC\n", &config).unwrap().status, BaseStatus::AC);
+        assert_eq!(judge_pattern("\\D+\n", "12\n", &config).unwrap().status, BaseStatus::WA);
+        assert_eq!(judge_pattern("YES|no\n", "yes\n", &config).unwrap().status, BaseStatus::AC);
+        assert_eq!(judge_pattern("YES\n", "yes\n", &regex()).unwrap().status, BaseStatus::WA);
+        let config = CompareConfig { mode: CompareMode::Glob, ..config };
+        assert_eq!(judge_pattern("Case #*\n", "CASE #1\n", &config).unwrap().status, BaseStatus::AC);
+    }
+
+    #[test]
+    fn only_the_output_is_folded_to_full_width() {
+        let config = CompareConfig {
+            normalize: Normalize { width: true, ..CompareConfig::default().normalize },
+            ..regex()
+        };
+        assert_eq!(judge_pattern("[0-9]+\n", "１２\n", &config).unwrap().status, BaseStatus::AC);
+        assert_eq!(judge_pattern("１\n", "1\n", &config).unwrap().status, BaseStatus::WA);
+    }
+}
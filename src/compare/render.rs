@@ -0,0 +1,355 @@
+use difference::{Changeset, Difference};
+
+#[derive(Clone)]
+pub struct RenderConfig {
+    pub context: usize,
+    pub max_lines: usize,
+    pub max_line_width: usize,
+    pub column_width: usize,
+    pub show_invisible: bool,
+    pub color: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Tag {
+    Same,
+    Rem,
+    Add,
+}
+
+/// One line of the changeset with its line number in the answer (`old`)
+/// and in the output (`new`).
+struct Line {
+    tag: Tag,
+    old: Option<usize>,
+    new: Option<usize>,
+    text: String,
+}
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+fn lines(changeset: &Changeset) -> Vec<Line> {
+    let (mut old, mut new) = (1usize, 1usize);
+    let mut result = vec![];
+    for diff in &changeset.diffs {
+        let (tag, text) = match diff {
+            Difference::Same(x) => (Tag::Same, x),
+            Difference::Rem(x) => (Tag::Rem, x),
+            Difference::Add(x) => (Tag::Add, x),
+        };
+        let parts: Vec<&str> = if changeset.split.is_empty() {
+            vec![text.as_str()]
+        } else {
+            text.split(changeset.split.as_str()).collect()
+        };
+        for part in parts {
+            let line = Line {
+                tag,
+                old: if tag != Tag::Add { Some(old) } else { None },
+                new: if tag != Tag::Rem { Some(new) } else { None },
+                text: part.to_string(),
+            };
+            if tag != Tag::Add { old += 1; }
+            if tag != Tag::Rem { new += 1; }
+            result.push(line);
+        }
+    }
+    result
+}
+
+/// Groups changed lines and their context into hunks, as index ranges into
+/// `lines`.
+fn hunks(lines: &[Line], context: usize) -> Vec<(usize, usize)> {
+    let mut result: Vec<(usize, usize)> = vec![];
+    for (index, line) in lines.iter().enumerate() {
+        if line.tag == Tag::Same {
+            continue;
+        }
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(lines.len());
+        match result.last_mut() {
+            Some(last) if last.1 >= start => last.1 = end,
+            _ => result.push((start, end)),
+        }
+    }
+    result
+}
+
+/// Makes tabs, carriage returns, other control characters and trailing
+/// spaces visible, and cuts the line to `max_line_width` characters.
+fn visible(text: &str, config: &RenderConfig) -> String {
+    let mut result = String::new();
+    if config.show_invisible {
+        let content = text.trim_end_matches(' ');
+        for c in content.chars() {
+            match c {
+                '\t' => result.push('→'),
+                '\r' => result.push('␍'),
+                c if (c as u32) < 0x20 => result.push(char::from_u32(0x2400 + c as u32).unwrap_or('?')),
+                c => result.push(c),
+            }
+        }
+        result.extend(std::iter::repeat_n('·', text.len() - content.len()));
+    } else {
+        result.push_str(text);
+    }
+    if result.chars().count() > config.max_line_width {
+        result = result.chars().take(config.max_line_width).collect::<String>() + "…";
+    }
+    result
+}
+
+fn number(value: Option<usize>) -> String {
+    value.map(|x| x.to_string()).unwrap_or_default()
+}
+
+fn paint(text: &str, color: &str, config: &RenderConfig) -> String {
+    if config.color {
+        format!("{}{}{}", color, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Renders the changeset as a unified diff of the answer (`-`) against the
+/// output (`+`), with both line numbers in front of every line.
+pub fn unified(changeset: &Changeset, config: &RenderConfig) -> String {
+    let lines = lines(changeset);
+    let mut result = vec!["--- answer".to_string(), "+++ output".to_string()];
+    for (start, end) in hunks(&lines, config.context) {
+        let hunk = &lines[start..end];
+        let old_start = hunk.iter().find_map(|x| x.old).unwrap_or(0);
+        let new_start = hunk.iter().find_map(|x| x.new).unwrap_or(0);
+        let old_len = hunk.iter().filter(|x| x.old.is_some()).count();
+        let new_len = hunk.iter().filter(|x| x.new.is_some()).count();
+        result.push(paint(
+            &format!("@@ -{},{} +{},{} @@", old_start, old_len, new_start, new_len), CYAN, config
+        ));
+        for line in hunk {
+            let (sign, color) = match line.tag {
+                Tag::Same => (' ', ""),
+                Tag::Rem => ('-', RED),
+                Tag::Add => ('+', GREEN),
+            };
+            let text = format!(
+                "{}{:>5} {:>5} | {}", sign, number(line.old), number(line.new), visible(&line.text, config)
+            );
+            result.push(if color.is_empty() { text } else { paint(&text, color, config) });
+        }
+        if result.len() > config.max_lines {
+            break;
+        }
+    }
+    if result.len() > config.max_lines {
+        result.truncate(config.max_lines);
+        result.push("... (truncated)".to_string());
+    }
+    result.join("\n")
+}
+
+/// Pairs removed and added lines of every hunk into rows of
+/// `(answer line, output line)`.
+fn rows(hunk: &[Line]) -> Vec<(Option<&Line>, Option<&Line>)> {
+    let mut result = vec![];
+    let mut index = 0;
+    while index < hunk.len() {
+        if hunk[index].tag == Tag::Same {
+            result.push((Some(&hunk[index]), Some(&hunk[index])));
+            index += 1;
+            continue;
+        }
+        let removed = hunk[index..].iter().take_while(|x| x.tag == Tag::Rem).collect::<Vec<_>>();
+        let added = hunk[index + removed.len()..].iter().take_while(|x| x.tag == Tag::Add).collect::<Vec<_>>();
+        for i in 0..removed.len().max(added.len()) {
+            result.push((removed.get(i).copied(), added.get(i).copied()));
+        }
+        index += removed.len() + added.len();
+    }
+    result
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    format!("{}{}", text, " ".repeat(width.saturating_sub(len)))
+}
+
+/// Renders the answer on the left and the output on the right, coloring
+/// the lines that differ.
+pub fn side_by_side(changeset: &Changeset, config: &RenderConfig) -> String {
+    let lines = lines(changeset);
+    let config = &RenderConfig {
+        max_line_width: config.max_line_width.min(config.column_width),
+        ..config.clone()
+    };
+    let width = config.max_line_width + 1;
+    let mut result = vec![format!("{:>5} {} | {:>5} {}", "", pad("answer", width), "", "output")];
+    for (start, end) in hunks(&lines, config.context) {
+        result.push(paint(&format!("{:>5} {} | {:>5}", "...", pad("", width), "..."), CYAN, config));
+        for (left, right) in rows(&lines[start..end]) {
+            let changed = left.map(|x| x.tag) != Some(Tag::Same);
+            let left_text = pad(&left.map(|x| visible(&x.text, config)).unwrap_or_default(), width);
+            let right_text = right.map(|x| visible(&x.text, config)).unwrap_or_default();
+            let left_number = left.and_then(|x| x.old);
+            let right_number = right.and_then(|x| x.new);
+            if changed {
+                result.push(format!(
+                    "{:>5} {} | {:>5} {}",
+                    number(left_number), paint(&left_text, RED, config),
+                    number(right_number), paint(&right_text, GREEN, config)
+                ));
+            } else {
+                result.push(format!(
+                    "{:>5} {} | {:>5} {}", number(left_number), left_text, number(right_number), right_text
+                ));
+            }
+        }
+        if result.len() > config.max_lines {
+            break;
+        }
+    }
+    if result.len() > config.max_lines {
+        result.truncate(config.max_lines);
+        result.push("... (truncated)".to_string());
+    }
+    result.join("\n")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Renders the hunks as an HTML table fragment. Rows carry the classes
+/// `same`, `rem`, `add` and `gap` for styling.
+pub fn html(changeset: &Changeset, config: &RenderConfig) -> String {
+    let lines = lines(changeset);
+    let mut rows = vec![];
+    for (start, end) in hunks(&lines, config.context) {
+        rows.push("<tr class=\"gap\"><td class=\"ln\"></td><td class=\"ln\"></td><td class=\"text\">…</td></tr>".to_string());
+        for line in &lines[start..end] {
+            let class = match line.tag {
+                Tag::Same => "same",
+                Tag::Rem => "rem",
+                Tag::Add => "add",
+            };
+            rows.push(format!(
+                "<tr class=\"{}\"><td class=\"ln\">{}</td><td class=\"ln\">{}</td><td class=\"text\">{}</td></tr>",
+                class, number(line.old), number(line.new), escape(&visible(&line.text, config))
+            ));
+        }
+        if rows.len() > config.max_lines {
+            break;
+        }
+    }
+    if rows.len() > config.max_lines {
+        rows.truncate(config.max_lines);
+        rows.push("<tr class=\"gap\"><td class=\"ln\"></td><td class=\"ln\"></td><td class=\"text\">(truncated)</td></tr>".to_string());
+    }
+    format!("<table class=\"diff\">\n{}\n</table>", rows.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain() -> RenderConfig {
+        RenderConfig { context: 1, color: false, ..RenderConfig::default() }
+    }
+
+    #[test]
+    fn unified_shows_both_line_numbers() {
+        let changeset = Changeset::new("a\nb\nc", "a\nx\nc", "\n");
+        assert_eq!(unified(&changeset, &plain()), [
+            "--- answer",
+            "+++ output",
+            "@@ -1,3 +1,3 @@",
+            "     1     1 | a",
+            "-    2       | b",
+            "+          2 | x",
+            "     3     3 | c",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn distant_changes_get_separate_hunks() {
+        let answer = (1..=9).map(|x| x.to_string()).collect::<Vec<_>>().join("\n");
+        let output = answer.replace('2', "two").replace('8', "eight");
+        let diff = unified(&Changeset::new(&answer, &output, "\n"), &plain());
+        let headers = diff.lines().filter(|x| x.starts_with("@@")).collect::<Vec<_>>();
+        assert_eq!(headers, ["@@ -1,3 +1,3 @@", "@@ -7,3 +7,3 @@"]);
+        assert!(!diff.contains("| 5"));
+        let diff = unified(&Changeset::new(&answer, &output, "\n"), &RenderConfig { context: 3, ..plain() });
+        assert_eq!(diff.lines().filter(|x| x.starts_with("@@")).count(), 1);
+    }
+
+    #[test]
+    fn invisible_characters_are_shown_and_long_lines_cut() {
+        let config = RenderConfig { max_line_width: 8, ..plain() };
+        assert_eq!(visible("a\tb\r  ", &config), "a→b␍··");
+        assert_eq!(visible("\x01", &config), "␁");
+        assert_eq!(visible("0123456789", &config), "01234567…");
+        let config = RenderConfig { show_invisible: false, ..plain() };
+        assert_eq!(visible("a\tb ", &config), "a\tb ");
+    }
+
+    #[test]
+    fn long_diffs_are_truncated() {
+        let answer = (0..50).map(|x| x.to_string()).collect::<Vec<_>>().join("\n");
+        let output = (0..50).map(|x| format!("{}!", x)).collect::<Vec<_>>().join("\n");
+        let changeset = Changeset::new(&answer, &output, "\n");
+        let config = RenderConfig { max_lines: 10, ..plain() };
+        let diff = unified(&changeset, &config);
+        assert_eq!(diff.lines().count(), 11);
+        assert!(diff.ends_with("... (truncated)"));
+        let diff = side_by_side(&changeset, &config);
+        assert_eq!(diff.lines().count(), 11);
+        assert!(diff.ends_with("... (truncated)"));
+        assert!(html(&changeset, &config).contains("(truncated)"));
+    }
+
+    #[test]
+    fn side_by_side_pairs_removed_and_added_lines() {
+        let changeset = Changeset::new("a\nb\nc", "a\nx\ny\nc", "\n");
+        let config = RenderConfig { column_width: 4, ..plain() };
+        assert_eq!(side_by_side(&changeset, &config), [
+            "      answer |       output",
+            "  ...       |   ...",
+            "    1 a     |     1 a",
+            "    2 b     |     2 x",
+            "            |     3 y",
+            "    3 c     |     4 c",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn colors_are_only_added_when_enabled() {
+        let changeset = Changeset::new("a", "b", "\n");
+        assert!(!unified(&changeset, &plain()).contains('\x1b'));
+        let diff = unified(&changeset, &RenderConfig { color: true, ..plain() });
+        assert!(diff.contains(&format!("{}-", RED)));
+        assert!(diff.contains(&format!("{}+", GREEN)));
+    }
+
+    #[test]
+    fn html_escapes_the_text() {
+        let changeset = Changeset::new("<a href='x'>&</a>", "\"", "\n");
+        let table = html(&changeset, &plain());
+        assert!(table.starts_with("<table class=\"diff\">"));
+        assert!(table.contains("<tr class=\"rem\"><td class=\"ln\">1</td><td class=\"ln\"></td><td class=\"text\">&lt;a href=&#39;x&#39;&gt;&amp;&lt;/a&gt;</td></tr>"));
+        assert!(table.contains("<td class=\"text\">&quot;</td>"));
+        assert!(!table.contains("<a "));
+    }
+
+    #[test]
+    fn identical_input_has_no_hunks() {
+        let changeset = Changeset::new("a\nb", "a\nb", "\n");
+        assert_eq!(unified(&changeset, &plain()), "--- answer\n+++ output");
+        assert_eq!(html(&changeset, &plain()), "<table class=\"diff\">\n\n</table>");
+    }
+}
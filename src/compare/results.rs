@@ -1,15 +1,18 @@
 use std::sync::Arc;
 
 use difference::{Changeset, Difference};
+use crate::nsjail_judger::result::JudgerResult;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum BaseStatus {
     AC,
     WA,
+    PE,
     TLE,
     MLE,
     OLE,
     CLE,
+    SE,
     UKE,
     PENDING,
 }
@@ -20,6 +23,16 @@ pub struct BaseResult {
     pub time: i32,
     pub memory: i32,
     pub info: Option<String>,
+    /// Proportion of matching lines or tokens, when scoring is enabled.
+    pub score: Option<f64>,
+}
+
+/// Verdict of a single comparison, before it is merged into a `BaseResult`.
+pub struct CompareResult {
+    pub status: BaseStatus,
+    pub changeset: Option<Changeset>,
+    pub info: Option<String>,
+    pub score: Option<f64>,
 }
 
 pub fn clone_changeset(changeset: &Changeset) -> Changeset {
@@ -49,6 +62,31 @@ impl BaseResult {
         }
         self.changeset = Some(clone_changeset(changeset));
     }
+
+    pub fn update_by_compare(&mut self, result: &CompareResult) {
+        if self.status != BaseStatus::PENDING {
+            return;
+        }
+        self.status = result.status;
+        self.changeset = result.changeset.as_ref().map(clone_changeset);
+        self.info = result.info.clone();
+        self.score = result.score;
+    }
+
+    /// Takes time and memory from a sandboxed run and reports OLE when the
+    /// program failed because its workspace ran out of space or inodes.
+    pub fn update_by_judger(&mut self, result: &JudgerResult) {
+        self.time = (result.time * 1000.0) as i32;
+        self.memory = result.memory;
+        if self.status != BaseStatus::PENDING || !result.disk_limit_exceeded {
+            return;
+        }
+        self.status = BaseStatus::OLE;
+        self.info = Some(result.disk_usage.iter().map(|(path, usage)| format!(
+            "{}: {}/{} bytes, {}/{} inodes",
+            path, usage.peak_bytes, usage.max_bytes, usage.peak_inodes, usage.max_inodes
+        )).collect::<Vec<String>>().join("\n"));
+    }
 }
 
 
@@ -0,0 +1,110 @@
+use std::fs::File;
+use crate::compare::compare::CompareConfig;
+use crate::compare::stream::count_matches;
+use crate::error::Error;
+
+/// What the similarity score counts.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScoreUnit {
+    Lines,
+    Tokens,
+}
+
+/// Proportion of matching lines or tokens, between 0 and 1. Whatever the
+/// size of the texts, items are matched as multisets (see `count_matches`):
+/// one missing line does not shift every line after it into a mismatch,
+/// and the order of the lines is not scored.
+pub fn similarity(ans: &str, output: &str, config: &CompareConfig, unit: ScoreUnit) -> f64 {
+    // Reading from memory cannot fail.
+    let (matched, total) = count_matches(ans.as_bytes(), output.as_bytes(), config, unit).unwrap_or_default();
+    ratio(matched, total)
+}
+
+/// Like `similarity`, but streams both files, for files too large to load.
+pub fn similarity_file_stream(ans_path: &str, output_path: &str, config: &CompareConfig, unit: ScoreUnit) -> Result<f64, Error> {
+    let ans = File::open(ans_path).map_err(|e| Error::compare(ans_path, e))?;
+    let output = File::open(output_path).map_err(|e| Error::compare(output_path, e))?;
+    let (matched, total) = count_matches(ans, output, config, unit).map_err(|e| Error::compare(output_path, e))?;
+    Ok(ratio(matched, total))
+}
+
+fn ratio(matched: usize, total: usize) -> f64 {
+    if matched == total {
+        return 1.0;
+    }
+    matched as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch;
+    use std::path::PathBuf;
+
+    #[test]
+    fn identical_after_normalization_is_one() {
+        let config = CompareConfig::default();
+        assert_eq!(similarity("1\n2\n", "1\r\n2", &config, ScoreUnit::Lines), 1.0);
+        assert_eq!(similarity("", "", &config, ScoreUnit::Tokens), 1.0);
+        assert_eq!(similarity("1  2\n", "1 2", &config, ScoreUnit::Tokens), 1.0);
+    }
+
+    #[test]
+    fn a_missing_line_does_not_shift_the_rest() {
+        let config = CompareConfig::default();
+        assert_eq!(similarity("1\n2\n3\n4", "1\n3\n4", &config, ScoreUnit::Lines), 0.75);
+        assert_eq!(similarity("1\n2\n3\n4", "4\n3\n2\n1", &config, ScoreUnit::Lines), 1.0);
+        assert_eq!(similarity("1\n1\n2", "1\n2\n2\n", &config, ScoreUnit::Lines), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn the_rule_does_not_depend_on_the_size() {
+        let (small, large) = (CompareConfig::default(), CompareConfig { force_fast_compare: 0, ..CompareConfig::default() });
+        let ans = (0..200).map(|x| x.to_string()).collect::<Vec<String>>();
+        let output = ans[1..].join("\n");
+        let ans = ans.join("\n");
+        assert!(ans.len() < small.force_fast_compare);
+        for unit in [ScoreUnit::Lines, ScoreUnit::Tokens] {
+            assert_eq!(similarity(&ans, &output, &small, unit), 0.995);
+            assert_eq!(similarity(&ans, &output, &large, unit), 0.995);
+        }
+    }
+
+    #[test]
+    fn score_units_parse_by_name() {
+        assert_eq!("lines".parse::<ScoreUnit>().unwrap(), ScoreUnit::Lines);
+        assert_eq!("tokens".parse::<ScoreUnit>().unwrap(), ScoreUnit::Tokens);
+        assert!(matches!("words".parse::<ScoreUnit>(), Err(Error::Config { .. })));
+    }
+
+    #[test]
+    fn tokens_are_counted_across_lines() {
+        let config = CompareConfig::default();
+        assert_eq!(similarity("1 2\n3 4", "1 2 5\n4", &config, ScoreUnit::Tokens), 0.75);
+        assert_eq!(similarity("1 2", "", &config, ScoreUnit::Tokens), 0.0);
+        assert_eq!(similarity("", "1 2", &config, ScoreUnit::Tokens), 0.0);
+        assert_eq!(similarity("a b c d", "x y", &config, ScoreUnit::Tokens), 0.0);
+    }
+
+    #[test]
+    fn streamed_score_matches_the_in_memory_score() {
+        let config = CompareConfig::default();
+        let tmp = scratch("stream");
+        let path = &tmp.src;
+        let file = |name: &str, data: &str| -> PathBuf {
+            std::fs::write(path.join(name), data).unwrap();
+            path.join(name)
+        };
+        let texts = ["", "\n", "1\n2\n3\n", "1\n\n\n3\n", "1 2\n4 3", "1\r\n2 \n", "\n\n1\n2\n\n"];
+        for (i, ans) in texts.iter().enumerate() {
+            for (j, output) in texts.iter().enumerate() {
+                let (x, y) = (file(&format!("{}.ans", i), ans), file(&format!("{}.out", j), output));
+                for unit in [ScoreUnit::Lines, ScoreUnit::Tokens] {
+                    let streamed = similarity_file_stream(&x.to_string_lossy(), &y.to_string_lossy(), &config, unit).unwrap();
+                    assert_eq!(streamed, similarity(ans, output, &config, unit), "{:?} vs {:?}, {:?}", ans, output, unit);
+                }
+            }
+        }
+        assert!(similarity_file_stream(&path.join("none").to_string_lossy(), &path.join("0.out").to_string_lossy(), &config, ScoreUnit::Lines).is_err());
+    }
+}
@@ -0,0 +1,887 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::hash::Hasher;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::compare::compare::{CompareConfig, CompareMode};
+use crate::compare::normalize::{blank_run, fold, Normalize, BOM};
+use crate::compare::score::ScoreUnit;
+use crate::error::Error;
+
+const EXCERPT: usize = 64;
+
+/// The first place where the output differs from the answer. `line` and
+/// `offset` point into the output; `None` stands for the end of a file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Mismatch {
+    pub line: usize,
+    pub offset: u64,
+    pub expected: Option<Vec<u8>>,
+    pub found: Option<Vec<u8>>,
+    pub binary: bool,
+}
+
+/// Shows valid UTF-8 as a quoted string and anything else, or anything from
+/// a binary comparison, as hex bytes.
+fn excerpt(value: &Option<Vec<u8>>, binary: bool) -> String {
+    let data = match value {
+        Some(x) => x,
+        None => return "end of file".to_string(),
+    };
+    match std::str::from_utf8(data).ok().filter(|_| !binary) {
+        Some(x) if x.chars().count() > EXCERPT => format!("{:?}...", x.chars().take(EXCERPT).collect::<String>()),
+        Some(x) => format!("{:?}", x),
+        None => format!(
+            "hex [{}]{}",
+            data.iter().take(EXCERPT / 4).map(|x| format!("{:02x}", x)).collect::<Vec<String>>().join(" "),
+            if data.len() > EXCERPT / 4 { " ..." } else { "" }
+        ),
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f, "line {}, byte {}: expected {}, found {}",
+            self.line, self.offset, excerpt(&self.expected, self.binary), excerpt(&self.found, self.binary)
+        )
+    }
+}
+
+/// Longest part of a line or a token kept for a mismatch message.
+const CAPTURE: usize = EXCERPT * 4;
+/// Largest piece of a line or a token read at a time.
+const CHUNK: usize = 8192;
+
+/// Position of a line or a token in the stream. Its content is read
+/// through `Chunks` while it is the open one.
+struct Segment {
+    line: usize,
+    offset: u64,
+    empty: bool,
+}
+
+/// Length of the longest prefix of `data` that can be folded before the
+/// rest of the line or token is known. Width folding maps single
+/// characters, but composition and the final sigma of case folding depend
+/// on the characters around, so those are only folded up to a whitespace.
+fn foldable(data: &[u8], policy: &Normalize) -> usize {
+    if policy.unicode.is_some() || policy.ignore_case {
+        return data.iter().rposition(|c| c.is_ascii_whitespace()).unwrap_or(0);
+    }
+    if !policy.width {
+        return data.len();
+    }
+    // Leaves a character cut at the end for the next piece.
+    let tail = data.len().saturating_sub(4);
+    match data[tail..].iter().rposition(|&c| c & 0xc0 != 0x80).map(|x| x + tail) {
+        Some(index) if data.len() - index < (data[index].leading_ones() as usize).max(1) => index,
+        _ => data.len(),
+    }
+}
+
+/// Normalized bytes of the open line or token that were not consumed yet,
+/// and the start of what was consumed.
+#[derive(Default)]
+struct Pending {
+    data: Vec<u8>,
+    position: usize,
+    captured: Vec<u8>,
+}
+
+impl Pending {
+    fn rest(&self) -> &[u8] {
+        &self.data[self.position..]
+    }
+
+    fn consume(&mut self, amount: usize) {
+        let kept = CAPTURE.saturating_sub(self.captured.len()).min(amount);
+        self.captured.extend_from_slice(&self.data[self.position..self.position + kept]);
+        self.position += amount;
+    }
+
+    fn replace(&mut self, data: Vec<u8>) {
+        self.data = data;
+        self.position = 0;
+    }
+}
+
+/// The open line or token of a stream, read in normalized chunks like a
+/// `BufRead`. An empty chunk marks its end.
+trait Chunks {
+    fn fill(&mut self) -> io::Result<&[u8]>;
+    fn consume(&mut self, amount: usize);
+    /// The first `CAPTURE` bytes consumed from the open line or token.
+    fn captured(&self) -> &[u8];
+
+    fn skip(&mut self) -> io::Result<()> {
+        loop {
+            let len = self.fill()?.len();
+            if len == 0 {
+                return Ok(());
+            }
+            self.consume(len);
+        }
+    }
+
+    /// The start of the open line or token, reading on as far as needed.
+    fn excerpt(&mut self) -> io::Result<Vec<u8>> {
+        while self.captured().len() < CAPTURE {
+            let len = self.fill()?.len();
+            if len == 0 {
+                break;
+            }
+            self.consume(len.min(CAPTURE - self.captured().len()));
+        }
+        Ok(self.captured().to_vec())
+    }
+
+    /// Hashes the rest of the open line or token.
+    fn digest(&mut self) -> io::Result<u64> {
+        let mut hasher = DefaultHasher::new();
+        loop {
+            let chunk = self.fill()?;
+            if chunk.is_empty() {
+                return Ok(hasher.finish());
+            }
+            hasher.write(chunk);
+            let len = chunk.len();
+            self.consume(len);
+        }
+    }
+}
+
+/// Splits a stream at line endings the same way `str::split` does. The
+/// open line is handed out in chunks of at most `CHUNK` bytes, so a long
+/// line is never held as a whole; only trailing whitespace and text waiting
+/// to be folded are held back until the rest of the line is known.
+struct Lines<'a, R: Read> {
+    reader: BufReader<R>,
+    line: usize,
+    offset: u64,
+    done: bool,
+    /// Whether the reader is still inside the open line.
+    open: bool,
+    /// Whether a line ending followed the open line.
+    ended: bool,
+    folding: Vec<u8>,
+    spaces: Vec<u8>,
+    pending: Pending,
+    config: &'a CompareConfig,
+}
+
+impl<'a, R: Read> Lines<'a, R> {
+    fn new(reader: R, config: &'a CompareConfig) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            line: 0,
+            offset: 0,
+            done: false,
+            open: false,
+            ended: false,
+            folding: vec![],
+            spaces: vec![],
+            pending: Pending::default(),
+            config,
+        }
+    }
+
+    /// Reads the next piece of the open line into `folding`, and consumes
+    /// its line ending once it is reached.
+    fn read_piece(&mut self) -> io::Result<()> {
+        let line_endings = self.config.normalize.line_endings;
+        let buffer = self.reader.fill_buf()?;
+        if buffer.is_empty() {
+            self.open = false;
+            self.done = true;
+            return Ok(());
+        }
+        let limit = buffer.len().min(CHUNK);
+        match buffer[..limit].iter().position(|&c| c == b'\n' || (line_endings && c == b'\r')) {
+            Some(index) => {
+                self.folding.extend_from_slice(&buffer[..index]);
+                let cr = buffer[index] == b'\r';
+                self.reader.consume(index + 1);
+                self.offset += index as u64 + 1;
+                if cr && self.reader.fill_buf()?.first() == Some(&b'\n') {
+                    self.reader.consume(1);
+                    self.offset += 1;
+                }
+                self.open = false;
+                self.ended = true;
+            },
+            None => {
+                self.folding.extend_from_slice(&buffer[..limit]);
+                self.reader.consume(limit);
+                self.offset += limit as u64;
+            },
+        }
+        Ok(())
+    }
+
+    /// Normalizes what was read of the open line the way `normalize_line`
+    /// does, holding back what depends on the rest of the line.
+    fn normalize_piece(&mut self) -> Vec<u8> {
+        let policy = &self.config.normalize;
+        let len = if self.open { foldable(&self.folding, policy) } else { self.folding.len() };
+        let mut data = self.folding.drain(..len).collect::<Vec<u8>>();
+        fold(&mut data, policy);
+        if policy.trailing_whitespace {
+            let mut result = vec![];
+            match data.iter().rposition(|c| !c.is_ascii_whitespace()) {
+                Some(index) => {
+                    result.append(&mut self.spaces);
+                    result.extend_from_slice(&data[..=index]);
+                    self.spaces.extend_from_slice(&data[index + 1..]);
+                },
+                None => self.spaces.append(&mut data),
+            }
+            if !self.open {
+                self.spaces.clear();
+            }
+            return result;
+        }
+        if self.config.ignore_end_of_line_space {
+            let mut result = std::mem::take(&mut self.spaces);
+            result.append(&mut data);
+            if result.last() == Some(&b' ') && (self.open || self.ended) {
+                self.spaces.extend(result.pop());
+            }
+            if !self.open {
+                self.spaces.clear();
+            }
+            return result;
+        }
+        data
+    }
+
+    /// Starts the next line, skipping what is left of the open one.
+    fn next(&mut self) -> io::Result<Option<Segment>> {
+        self.skip()?;
+        if self.done {
+            return Ok(None);
+        }
+        self.line += 1;
+        if self.line == 1 && self.config.normalize.bom && self.reader.fill_buf()?.starts_with(BOM) {
+            self.reader.consume(BOM.len());
+            self.offset += BOM.len() as u64;
+        }
+        let offset = self.offset;
+        self.open = true;
+        self.ended = false;
+        self.pending.captured.clear();
+        let empty = self.fill()?.is_empty();
+        Ok(Some(Segment { line: self.line, offset, empty }))
+    }
+}
+
+impl<R: Read> Chunks for Lines<'_, R> {
+    fn fill(&mut self) -> io::Result<&[u8]> {
+        while self.pending.rest().is_empty() && self.open {
+            self.read_piece()?;
+            let data = self.normalize_piece();
+            self.pending.replace(data);
+        }
+        Ok(self.pending.rest())
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pending.consume(amount);
+    }
+
+    fn captured(&self) -> &[u8] {
+        &self.pending.captured
+    }
+}
+
+/// A maximal run of empty lines and the line that follows it.
+struct Run {
+    count: usize,
+    first: bool,
+    next: Option<Segment>,
+    captured: Option<Segment>,
+}
+
+impl Run {
+    /// Consumes the empty lines starting at `current`, keeping the one at
+    /// index `capture` so that its position can be reported.
+    fn read<R: Read>(lines: &mut Lines<'_, R>, mut current: Option<Segment>, capture: usize) -> io::Result<Self> {
+        let first = current.as_ref().map(|x| x.line == 1).unwrap_or(false);
+        let (mut count, mut captured) = (0, None);
+        loop {
+            match current {
+                Some(segment) if segment.empty => {
+                    if count == capture {
+                        captured = Some(segment);
+                    }
+                    count += 1;
+                    current = lines.next()?;
+                },
+                _ => break,
+            }
+        }
+        Ok(Self { count, first, next: current, captured })
+    }
+
+    /// Number of empty lines left after normalization.
+    fn effective(&self, config: &CompareConfig) -> usize {
+        blank_run(self.count, self.first, self.next.is_none(), config)
+    }
+}
+
+/// Yields a hash of each line of a stream as `normalize` would leave it,
+/// applying the blank line rules on top of `Lines`.
+struct NormalizedLines<'a, R: Read> {
+    lines: Lines<'a, R>,
+    blank: usize,
+    pending: Option<Segment>,
+    started: bool,
+}
+
+impl<'a, R: Read> NormalizedLines<'a, R> {
+    fn new(reader: R, config: &'a CompareConfig) -> Self {
+        Self { lines: Lines::new(reader, config), blank: 0, pending: None, started: false }
+    }
+
+    fn next(&mut self) -> io::Result<Option<u64>> {
+        loop {
+            if self.blank > 0 {
+                self.blank -= 1;
+                return Ok(Some(DefaultHasher::new().finish()));
+            }
+            let current = if self.started {
+                self.pending.take()
+            } else {
+                self.started = true;
+                self.lines.next()?
+            };
+            match current {
+                None => return Ok(None),
+                Some(segment) if !segment.empty => {
+                    let digest = self.lines.digest()?;
+                    self.pending = self.lines.next()?;
+                    return Ok(Some(digest));
+                },
+                empty => {
+                    let run = Run::read(&mut self.lines, empty, usize::MAX)?;
+                    self.blank = run.effective(self.lines.config);
+                    self.pending = run.next;
+                },
+            }
+        }
+    }
+}
+
+/// Compares the rest of the open lines or tokens of both sides chunk by
+/// chunk. Returns the length of their common part when they differ.
+fn compare_open(ans: &mut impl Chunks, output: &mut impl Chunks) -> io::Result<Option<u64>> {
+    let mut common = 0;
+    loop {
+        let x = ans.fill()?;
+        let y = output.fill()?;
+        let len = x.len().min(y.len());
+        let same = x[..len].iter().zip(&y[..len]).take_while(|(p, q)| p == q).count();
+        let equal = x.is_empty() && y.is_empty();
+        ans.consume(same);
+        output.consume(same);
+        common += same as u64;
+        if equal {
+            return Ok(None);
+        }
+        if same < len || len == 0 {
+            return Ok(Some(common));
+        }
+    }
+}
+
+fn mismatch<R: Read>(expected: Option<Vec<u8>>, found: Option<&Segment>, text: Vec<u8>, output: &Lines<'_, R>) -> Mismatch {
+    Mismatch {
+        line: found.map(|x| x.line).unwrap_or(output.line),
+        offset: found.map(|x| x.offset).unwrap_or(output.offset),
+        expected,
+        found: found.map(|_| text),
+        binary: false,
+    }
+}
+
+fn compare_lines(ans: impl Read, output: impl Read, config: &CompareConfig) -> io::Result<Option<Mismatch>> {
+    let mut ans = Lines::new(ans, config);
+    let mut output = Lines::new(output, config);
+    let (mut x, mut y) = (ans.next()?, output.next()?);
+    loop {
+        if let (Some(a), Some(b)) = (&x, &y) {
+            if !a.empty && !b.empty {
+                if let Some(common) = compare_open(&mut ans, &mut output)? {
+                    let mut result = mismatch(Some(ans.excerpt()?), Some(b), output.excerpt()?, &output);
+                    result.offset += common;
+                    return Ok(Some(result));
+                }
+                x = ans.next()?;
+                y = output.next()?;
+                continue;
+            }
+        }
+        let run_ans = Run::read(&mut ans, x, usize::MAX)?;
+        let expected = run_ans.effective(config);
+        let run_output = Run::read(&mut output, y, expected)?;
+        let found = run_output.effective(config);
+        if expected < found {
+            let text = match run_ans.next {
+                Some(_) => Some(ans.excerpt()?),
+                None => None,
+            };
+            return Ok(Some(mismatch(text, run_output.captured.as_ref(), vec![], &output)));
+        }
+        if expected > found {
+            return Ok(Some(mismatch(Some(vec![]), run_output.next.as_ref(), output.excerpt()?, &output)));
+        }
+        match (run_ans.next, run_output.next) {
+            (None, None) => return Ok(None),
+            (Some(a), Some(b)) => {
+                x = Some(a);
+                y = Some(b);
+            },
+            (a, b) => {
+                let text = match a {
+                    Some(_) => Some(ans.excerpt()?),
+                    None => None,
+                };
+                return Ok(Some(mismatch(text, b.as_ref(), output.excerpt()?, &output)));
+            },
+        }
+    }
+}
+
+/// Splits a stream into whitespace separated tokens. Like `Lines`, the
+/// open token is handed out in chunks; it is only held whole when Unicode
+/// or case folding needs all of it.
+struct Tokens<R: Read> {
+    reader: BufReader<R>,
+    line: usize,
+    offset: u64,
+    policy: Normalize,
+    /// Whether the reader is still inside the open token.
+    open: bool,
+    /// Line and offset where the open token starts.
+    start: (usize, u64),
+    folding: Vec<u8>,
+    pending: Pending,
+}
+
+impl<R: Read> Tokens<R> {
+    fn new(reader: R, config: &CompareConfig) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut offset = 0;
+        if config.normalize.bom && reader.fill_buf()?.starts_with(BOM) {
+            reader.consume(BOM.len());
+            offset = BOM.len() as u64;
+        }
+        Ok(Self {
+            reader,
+            line: 1,
+            offset,
+            policy: config.normalize,
+            open: false,
+            start: (1, offset),
+            folding: vec![],
+            pending: Pending::default(),
+        })
+    }
+
+    /// Skips the whitespace before the next token in the stream. Returns
+    /// false at the end of the stream.
+    fn open_token(&mut self) -> io::Result<bool> {
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(false);
+            }
+            let blank = buffer.iter().take_while(|c| c.is_ascii_whitespace()).count();
+            let rest = buffer.len() - blank;
+            self.line += buffer[..blank].iter().filter(|&&c| c == b'\n').count();
+            self.offset += blank as u64;
+            self.reader.consume(blank);
+            if rest > 0 {
+                self.start = (self.line, self.offset);
+                self.open = true;
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Reads and folds the next piece of the open token.
+    fn read_piece(&mut self) -> io::Result<()> {
+        let buffer = self.reader.fill_buf()?;
+        let limit = buffer.len().min(CHUNK);
+        let len = buffer[..limit].iter().position(|c| c.is_ascii_whitespace()).unwrap_or(limit);
+        self.folding.extend_from_slice(&buffer[..len]);
+        self.reader.consume(len);
+        self.offset += len as u64;
+        self.open = len == limit && limit > 0;
+        let len = if self.open { foldable(&self.folding, &self.policy) } else { self.folding.len() };
+        let mut data = self.folding.drain(..len).collect::<Vec<u8>>();
+        fold(&mut data, &self.policy);
+        self.pending.replace(data);
+        Ok(())
+    }
+
+    /// Starts the next token, skipping what is left of the open one.
+    /// Folding may turn characters such as the ideographic space into ASCII
+    /// whitespace, so a folded token can split into several, which all
+    /// keep its position.
+    fn next(&mut self) -> io::Result<Option<Segment>> {
+        self.skip()?;
+        loop {
+            let blank = self.pending.rest().iter().take_while(|c| c.is_ascii_whitespace()).count();
+            self.pending.position += blank;
+            if !self.fill()?.is_empty() {
+                self.pending.captured.clear();
+                return Ok(Some(Segment { line: self.start.0, offset: self.start.1, empty: false }));
+            }
+            if self.pending.rest().is_empty() && !self.open_token()? {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+impl<R: Read> Tokens<R> {
+    fn next_digest(&mut self) -> io::Result<Option<u64>> {
+        match self.next()? {
+            Some(_) => self.digest().map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: Read> Chunks for Tokens<R> {
+    fn fill(&mut self) -> io::Result<&[u8]> {
+        while self.pending.rest().is_empty() && self.open {
+            self.read_piece()?;
+        }
+        let rest = self.pending.rest();
+        let len = rest.iter().position(|c| c.is_ascii_whitespace()).unwrap_or(rest.len());
+        Ok(&rest[..len])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pending.consume(amount);
+    }
+
+    fn captured(&self) -> &[u8] {
+        &self.pending.captured
+    }
+}
+
+fn compare_tokens(ans: impl Read, output: impl Read, config: &CompareConfig) -> io::Result<Option<Mismatch>> {
+    let mut ans = Tokens::new(ans, config)?;
+    let mut output = Tokens::new(output, config)?;
+    loop {
+        let (a, b) = (ans.next()?, output.next()?);
+        if a.is_none() && b.is_none() {
+            return Ok(None);
+        }
+        if a.is_some() && b.is_some() && compare_open(&mut ans, &mut output)?.is_none() {
+            continue;
+        }
+        let expected = match a {
+            Some(_) => Some(ans.excerpt()?),
+            None => None,
+        };
+        let found = match b {
+            Some(_) => Some(output.excerpt()?),
+            None => None,
+        };
+        return Ok(Some(Mismatch {
+            line: b.as_ref().map(|x| x.line).unwrap_or(output.line),
+            offset: b.as_ref().map(|x| x.offset).unwrap_or(output.offset),
+            expected,
+            found,
+            binary: false,
+        }));
+    }
+}
+
+/// Compares byte by byte. The excerpts start at the first differing byte.
+fn compare_bytes(ans: impl Read, output: impl Read) -> io::Result<Option<Mismatch>> {
+    let mut ans = BufReader::new(ans);
+    let mut output = BufReader::new(output);
+    let (mut offset, mut line) = (0u64, 1usize);
+    loop {
+        let x = ans.fill_buf()?;
+        let y = output.fill_buf()?;
+        let len = x.len().min(y.len());
+        let common = x[..len].iter().zip(&y[..len]).take_while(|(p, q)| p == q).count();
+        line += y[..common].iter().filter(|&&c| c == b'\n').count();
+        offset += common as u64;
+        let finished = common < len || len == 0;
+        ans.consume(common);
+        output.consume(common);
+        if !finished {
+            continue;
+        }
+        let mut expected = vec![];
+        let mut found = vec![];
+        ans.take(EXCERPT as u64 / 4).read_to_end(&mut expected)?;
+        output.take(EXCERPT as u64 / 4).read_to_end(&mut found)?;
+        if expected.is_empty() && found.is_empty() {
+            return Ok(None);
+        }
+        return Ok(Some(Mismatch {
+            line,
+            offset,
+            expected: if expected.is_empty() { None } else { Some(expected) },
+            found: if found.is_empty() { None } else { Some(found) },
+            binary: true,
+        }));
+    }
+}
+
+/// Counts the items of the output that match an equal item of the answer,
+/// each answer item matching at most once, and the items of the longer
+/// side.
+fn count_common(
+    mut ans: impl FnMut() -> io::Result<Option<u64>>,
+    mut output: impl FnMut() -> io::Result<Option<u64>>,
+) -> io::Result<(usize, usize)> {
+    let (mut counts, mut expected) = (HashMap::new(), 0);
+    while let Some(item) = ans()? {
+        *counts.entry(item).or_insert(0usize) += 1;
+        expected += 1;
+    }
+    let (mut matched, mut found) = (0, 0);
+    while let Some(item) = output()? {
+        found += 1;
+        if let Some(count) = counts.get_mut(&item).filter(|x| **x > 0) {
+            *count -= 1;
+            matched += 1;
+        }
+    }
+    Ok((matched, expected.max(found)))
+}
+
+/// Matches the normalized lines or tokens of two streams as multisets, so
+/// a missing item does not shift the ones after it into a mismatch, while
+/// their order is not taken into account. Items are compared by their
+/// hashes, so besides one chunk of each side only a count per distinct
+/// answer item is held. Returns the number of matching items and the
+/// number of items on the longer side.
+pub fn count_matches(ans: impl Read, output: impl Read, config: &CompareConfig, unit: ScoreUnit) -> io::Result<(usize, usize)> {
+    match unit {
+        ScoreUnit::Lines => {
+            let mut ans = NormalizedLines::new(ans, config);
+            let mut output = NormalizedLines::new(output, config);
+            count_common(|| ans.next(), || output.next())
+        },
+        ScoreUnit::Tokens => {
+            let mut ans = Tokens::new(ans, config)?;
+            let mut output = Tokens::new(output, config)?;
+            count_common(|| ans.next_digest(), || output.next_digest())
+        },
+    }
+}
+
+/// Compares two streams without loading them into memory. Only a chunk of
+/// the current line (or token) of each side is held at a time. The
+/// unordered modes cannot be streamed and are compared line by line.
+pub fn compare_stream(ans: impl Read, output: impl Read, config: &CompareConfig) -> io::Result<Option<Mismatch>> {
+    match config.mode {
+        CompareMode::Tokens => compare_tokens(ans, output, config),
+        CompareMode::Binary => compare_bytes(ans, output),
+        _ => compare_lines(ans, output, config),
+    }
+}
+
+pub fn compare_file_stream(ans_path: &str, output_path: &str, config: &CompareConfig) -> Result<Option<Mismatch>, Error> {
+    let ans = File::open(ans_path).map_err(|e| Error::compare(ans_path, e))?;
+    let output = File::open(output_path).map_err(|e| Error::compare(output_path, e))?;
+    compare_stream(ans, output, config).map_err(|e| Error::compare(output_path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::normalize::normalize;
+
+    fn compare(ans: &str, output: &str, config: &CompareConfig) -> Option<Mismatch> {
+        compare_stream(ans.as_bytes(), output.as_bytes(), config).unwrap()
+    }
+
+    #[test]
+    fn agrees_with_the_in_memory_normalization() {
+        let texts = ["", "\n", "\n\n", "a", "a\n", "a\n\n", "a\n\n\n", "\na", "\n\na", "a\n\nb", "a\n\n\nb",
+            "a \n", "a  \n", "a\r\nb\r\n", "a\rb", "\u{feff}a", "a\t\n", "b\n"];
+        let configs = [
+            CompareConfig::default(),
+            CompareConfig { ignore_end_of_text_enters: Some(-1), ..CompareConfig::default() },
+            CompareConfig { ignore_end_of_text_enters: None, ignore_end_of_line_space: false, ..CompareConfig::default() },
+            CompareConfig {
+                normalize: Normalize {
+                    trailing_whitespace: true,
+                    collapse_blank_lines: true,
+                    leading_blank_lines: true,
+                    trailing_blank_lines: true,
+                    ..CompareConfig::default().normalize
+                },
+                ..CompareConfig::default()
+            },
+        ];
+        for config in &configs {
+            for ans in texts {
+                for output in texts {
+                    let expected = normalize(ans, config) == normalize(output, config);
+                    assert_eq!(compare(ans, output, config).is_none(), expected, "{:?} vs {:?}", ans, output);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mismatch_points_at_the_first_differing_byte() {
+        let config = CompareConfig::default();
+        let result = compare("1 2\n3 4\n", "1 2\n3 5\n", &config).unwrap();
+        assert_eq!((result.line, result.offset), (2, 6));
+        assert_eq!(result.expected.as_deref(), Some(&b"3 4"[..]));
+        assert_eq!(result.found.as_deref(), Some(&b"3 5"[..]));
+        assert_eq!(result.to_string(), "line 2, byte 6: expected \"3 4\", found \"3 5\"");
+        let result = compare("\u{feff}ab", "\u{feff}ac", &config).unwrap();
+        assert_eq!((result.line, result.offset), (1, 4));
+    }
+
+    #[test]
+    fn missing_and_extra_lines_are_reported_as_end_of_file() {
+        let config = CompareConfig::default();
+        let result = compare("a\nb\n", "a\n", &config).unwrap();
+        assert_eq!(result.expected.as_deref(), Some(&b"b"[..]));
+        assert_eq!(result.found, None);
+        assert!(result.to_string().ends_with("found end of file"));
+        let result = compare("a\n", "a\nb\n", &config).unwrap();
+        assert_eq!(result.line, 2);
+        assert_eq!(result.expected, None);
+        assert_eq!(result.found.as_deref(), Some(&b"b"[..]));
+    }
+
+    #[test]
+    fn tokens_ignore_all_whitespace() {
+        let config = CompareConfig { mode: CompareMode::Tokens, ..CompareConfig::default() };
+        assert_eq!(compare("1 2\n3\n", "  1\t2 3", &config), None);
+        assert_eq!(compare("", "\n \n", &config), None);
+        let result = compare("1 2\n3 4", "1 2\n\n3  5", &config).unwrap();
+        assert_eq!((result.line, result.offset), (3, 8));
+        assert_eq!(result.found.as_deref(), Some(&b"5"[..]));
+        let result = compare("1 2", "1", &config).unwrap();
+        assert_eq!((result.expected.as_deref(), result.found), (Some(&b"2"[..]), None));
+    }
+
+    #[test]
+    fn folded_tokens_can_split() {
+        let config = CompareConfig {
+            mode: CompareMode::Tokens,
+            normalize: Normalize { width: true, ..CompareConfig::default().normalize },
+            ..CompareConfig::default()
+        };
+        assert_eq!(compare("１ ２", "1\u{3000}2", &config), None);
+    }
+
+    #[test]
+    fn lines_much_larger_than_a_chunk_are_compared_in_pieces() {
+        let config = CompareConfig::default();
+        let ans = format!("1\n{}\n", "a".repeat(CHUNK * 10));
+        let mut output = ans.clone().into_bytes();
+        output[2 + CHUNK * 7 + 3] = b'b';
+        assert_eq!(compare(&ans, &ans, &config), None);
+        let result = compare_stream(ans.as_bytes(), &output[..], &config).unwrap().unwrap();
+        assert_eq!((result.line, result.offset), (2, 2 + CHUNK as u64 * 7 + 3));
+        assert_eq!(result.expected.unwrap(), vec![b'a'; CAPTURE]);
+        assert_eq!(result.found.unwrap(), vec![b'a'; CAPTURE]);
+        let result = compare(&ans, &ans[..ans.len() - 2], &config).unwrap();
+        assert_eq!((result.line, result.offset), (2, 2 + CHUNK as u64 * 10 - 1));
+    }
+
+    #[test]
+    fn held_back_text_spans_chunks() {
+        let spaces = format!("a{}\nb", " ".repeat(CHUNK * 2));
+        let config = CompareConfig {
+            normalize: Normalize { trailing_whitespace: true, ..CompareConfig::default().normalize },
+            ..CompareConfig::default()
+        };
+        assert_eq!(compare(&spaces, "a\nb", &config), None);
+        assert!(compare(&spaces, "a\nb", &CompareConfig::default()).is_some());
+        let inner = format!("{} b", "a".repeat(CHUNK - 1));
+        assert_eq!(compare(&inner, &inner, &CompareConfig::default()), None);
+        assert!(compare(&inner, &format!("{}b", "a".repeat(CHUNK - 1)), &CompareConfig::default()).is_some());
+        let config = CompareConfig {
+            normalize: Normalize { width: true, ..CompareConfig::default().normalize },
+            ..CompareConfig::default()
+        };
+        let wide = format!("{}１", "a".repeat(CHUNK - 1));
+        assert_eq!(compare(&wide, &format!("{}1", "a".repeat(CHUNK - 1)), &config), None);
+    }
+
+    #[test]
+    fn tokens_much_larger_than_a_chunk_are_compared_in_pieces() {
+        let config = CompareConfig { mode: CompareMode::Tokens, ..CompareConfig::default() };
+        let token = "x".repeat(CHUNK * 5);
+        assert_eq!(compare(&format!("1 {} 2", token), &format!("1\n{}\n2\n", token), &config), None);
+        let result = compare(&format!("1 {}", token), &format!("1 {}y", token), &config).unwrap();
+        assert_eq!((result.line, result.offset), (1, 2));
+        assert_eq!(result.found.unwrap().len(), CAPTURE);
+        let count = count_matches(format!("{} 1", token).as_bytes(), format!("{} 2", token).as_bytes(), &config, ScoreUnit::Tokens);
+        assert_eq!(count.unwrap(), (1, 2));
+    }
+
+    #[test]
+    fn invalid_utf8_is_shown_as_hex() {
+        let config = CompareConfig::default();
+        let result = compare_stream(&b"a\n"[..], &b"\xff\xfe\n"[..], &config).unwrap().unwrap();
+        assert_eq!(result.to_string(), "line 1, byte 0: expected \"a\", found hex [ff fe]");
+    }
+
+    #[test]
+    fn bytes_are_compared_without_normalization() {
+        let config = CompareConfig { mode: CompareMode::Binary, ..CompareConfig::default() };
+        assert_eq!(compare("", "", &config), None);
+        assert_eq!(compare("a\r\n", "a\r\n", &config), None);
+        let result = compare("a\r\n", "a\n", &config).unwrap();
+        assert_eq!((result.line, result.offset), (1, 1));
+        assert_eq!(result.expected.as_deref(), Some(&b"\r\n"[..]));
+        assert_eq!(result.to_string(), "line 1, byte 1: expected hex [0d 0a], found hex [0a]");
+    }
+
+    #[test]
+    fn bytes_report_the_line_and_a_length_mismatch() {
+        let config = CompareConfig { mode: CompareMode::Binary, ..CompareConfig::default() };
+        let result = compare("1\n2\n3", "1\n2\n3\n", &config).unwrap();
+        assert_eq!((result.line, result.offset), (3, 5));
+        assert_eq!((result.expected, result.found.as_deref()), (None, Some(&b"\n"[..])));
+        let result = compare("1\n2\n3", "1\n", &config).unwrap();
+        assert_eq!((result.line, result.offset, result.found.is_none()), (2, 2, true));
+        assert!(result.to_string().ends_with("found end of file"));
+    }
+
+    #[test]
+    fn bytes_beyond_the_buffer_are_compared() {
+        let config = CompareConfig { mode: CompareMode::Binary, ..CompareConfig::default() };
+        let ans = vec![0u8; 100_000];
+        let mut output = ans.clone();
+        output[70_000] = 1;
+        assert_eq!(compare_stream(&ans[..], &ans[..], &config).unwrap(), None);
+        let result = compare_stream(&ans[..], &output[..], &config).unwrap().unwrap();
+        assert_eq!(result.offset, 70_000);
+        assert_eq!(result.found.unwrap()[0], 1);
+    }
+
+    #[test]
+    fn count_matches_pairs_items_as_multisets() {
+        let config = CompareConfig::default();
+        let count = |ans: &str, output: &str, unit| count_matches(ans.as_bytes(), output.as_bytes(), &config, unit).unwrap();
+        assert_eq!(count("1\n2\n3\n", "1\n3\n", ScoreUnit::Lines), (2, 3));
+        assert_eq!(count("1\n\n\n2", "1\n\n2", ScoreUnit::Lines), (3, 4));
+        assert_eq!(count("1\n1\n2", "2\n2\n1", ScoreUnit::Lines), (2, 3));
+        assert_eq!(count("1 2\n3", "1\n2 4 5", ScoreUnit::Tokens), (2, 4));
+        assert_eq!(count("", "", ScoreUnit::Tokens), (0, 0));
+    }
+}
@@ -0,0 +1,323 @@
+use crate::compare::compare::{CompareConfig, CompareMode};
+use crate::compare::results::{BaseStatus, CompareResult};
+
+/// Options for the JSON, CSV and XML modes.
+#[derive(Clone)]
+pub struct StructureConfig {
+    /// Object keys must appear in the same order.
+    pub ordered_keys: bool,
+    /// Numbers are compared by value, so `1.50` equals `1.5`; otherwise by
+    /// their text.
+    pub numbers_by_value: bool,
+    /// Allowed absolute error, or relative error for values above 1.
+    pub tolerance: f64,
+    /// The first CSV row names the columns.
+    pub csv_header: bool,
+}
+
+/// A parsed document. JSON maps onto it directly; a CSV file is a list of
+/// rows and an XML file a tree of elements.
+enum Node {
+    Null,
+    Bool(bool),
+    Number(String),
+    Text(String),
+    List(Vec<Node>),
+    Map(Vec<(String, Node)>),
+    Element { name: String, attributes: Vec<(String, String)>, children: Vec<Node> },
+}
+
+impl Node {
+    fn describe(&self) -> String {
+        match self {
+            Node::Null => "null".to_string(),
+            Node::Bool(x) => x.to_string(),
+            Node::Number(x) => x.clone(),
+            Node::Text(x) if x.chars().count() > 64 => format!("{:?}...", x.chars().take(64).collect::<String>()),
+            Node::Text(x) => format!("{:?}", x),
+            Node::List(x) => format!("a list of {} element(s)", x.len()),
+            Node::Map(x) => format!("an object with {} key(s)", x.len()),
+            Node::Element { name, .. } => format!("<{}>", name),
+        }
+    }
+}
+
+/// CSV fields and XML text that look like numbers are compared as numbers.
+fn scalar(text: &str) -> Node {
+    let trimmed = text.trim();
+    if trimmed.parse::<f64>().is_ok() && trimmed.chars().any(|c| c.is_ascii_digit()) {
+        Node::Number(trimmed.to_string())
+    } else {
+        Node::Text(text.to_string())
+    }
+}
+
+fn from_json(value: serde_json::Value) -> Node {
+    match value {
+        serde_json::Value::Null => Node::Null,
+        serde_json::Value::Bool(x) => Node::Bool(x),
+        serde_json::Value::Number(x) => Node::Number(x.to_string()),
+        serde_json::Value::String(x) => Node::Text(x),
+        serde_json::Value::Array(x) => Node::List(x.into_iter().map(from_json).collect()),
+        serde_json::Value::Object(x) => Node::Map(x.into_iter().map(|(k, v)| (k, from_json(v))).collect()),
+    }
+}
+
+fn from_csv(text: &str, config: &StructureConfig) -> Result<Node, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(config.csv_header)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let headers = if config.csv_header {
+        Some(reader.headers().map_err(|e| e.to_string())?.iter().map(|x| x.to_string()).collect::<Vec<String>>())
+    } else {
+        None
+    };
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        rows.push(match &headers {
+            Some(headers) => Node::Map(headers.iter().cloned().zip(record.iter().map(scalar)).collect()),
+            None => Node::List(record.iter().map(scalar).collect()),
+        });
+    }
+    Ok(Node::List(rows))
+}
+
+fn from_xml(node: roxmltree::Node) -> Node {
+    let children = node.children().filter_map(|child| {
+        if child.is_element() {
+            Some(from_xml(child))
+        } else if child.is_text() {
+            child.text().map(|x| x.trim()).filter(|x| !x.is_empty()).map(scalar)
+        } else {
+            None
+        }
+    }).collect();
+    Node::Element {
+        name: node.tag_name().name().to_string(),
+        attributes: node.attributes().map(|x| (x.name().to_string(), x.value().to_string())).collect(),
+        children,
+    }
+}
+
+fn parse(text: &str, config: &CompareConfig) -> Result<Node, String> {
+    let text = if config.normalize.bom { text.trim_start_matches('\u{feff}') } else { text };
+    match config.mode {
+        CompareMode::Json => serde_json::from_str(text).map(from_json).map_err(|e| format!("invalid JSON: {}", e)),
+        CompareMode::Csv => from_csv(text, &config.structure).map_err(|e| format!("invalid CSV: {}", e)),
+        _ => roxmltree::Document::parse(text)
+            .map(|x| from_xml(x.root_element()))
+            .map_err(|e| format!("invalid XML: {}", e)),
+    }
+}
+
+fn numbers_equal(x: &str, y: &str, config: &StructureConfig) -> bool {
+    if !config.numbers_by_value {
+        return x == y;
+    }
+    match (x.parse::<f64>(), y.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a == b || (a - b).abs() <= config.tolerance * a.abs().max(1.0),
+        _ => x == y,
+    }
+}
+
+fn key_path(path: &str, key: &str) -> String {
+    let simple = !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if simple { format!("{}.{}", path, key) } else { format!("{}[{:?}]", path, key) }
+}
+
+fn compare_lists(x: &[Node], y: &[Node], path: &str, config: &StructureConfig) -> Option<String> {
+    let paths = (0..x.len().max(y.len())).map(|i| format!("{}[{}]", path, i));
+    compare_children(x, y, paths, path, "element", config)
+}
+
+fn compare_children(
+    x: &[Node], y: &[Node], paths: impl Iterator<Item = String>, path: &str, what: &str, config: &StructureConfig,
+) -> Option<String> {
+    for ((a, b), path) in x.iter().zip(y).zip(paths) {
+        if let Some(result) = compare(a, b, &path, config) {
+            return Some(result);
+        }
+    }
+    if x.len() != y.len() {
+        return Some(format!("{}: expected {} {}(s), found {}", path, x.len(), what, y.len()));
+    }
+    None
+}
+
+fn compare_maps(x: &[(String, Node)], y: &[(String, Node)], path: &str, config: &StructureConfig) -> Option<String> {
+    for (key, a) in x {
+        match y.iter().find(|(k, _)| k == key) {
+            Some((_, b)) => if let Some(result) = compare(a, b, &key_path(path, key), config) {
+                return Some(result);
+            },
+            None => return Some(format!("{}: missing key {:?}", path, key)),
+        }
+    }
+    if let Some((key, _)) = y.iter().find(|(k, _)| !x.iter().any(|(key, _)| key == k)) {
+        return Some(format!("{}: extra key {:?}", path, key));
+    }
+    if config.ordered_keys {
+        let position = x.iter().zip(y).position(|((a, _), (b, _))| a != b);
+        if let Some(index) = position {
+            return Some(format!(
+                "{}: expected key {:?} at position {}, found {:?}", path, x[index].0, index, y[index].0
+            ));
+        }
+    }
+    None
+}
+
+/// XPath-like paths for the children of an element: `name[n]` counts
+/// siblings with the same name from 1, text nodes are `text()[n]`.
+fn child_paths(children: &[Node], path: &str) -> Vec<String> {
+    let mut seen: Vec<(String, usize)> = vec![];
+    children.iter().map(|child| {
+        let name = match child {
+            Node::Element { name, .. } => name.clone(),
+            _ => "text()".to_string(),
+        };
+        let index = match seen.iter_mut().find(|(x, _)| *x == name) {
+            Some((_, count)) => { *count += 1; *count },
+            None => { seen.push((name.clone(), 1)); 1 },
+        };
+        format!("{}/{}[{}]", path, name, index)
+    }).collect()
+}
+
+fn compare_elements(x: &Node, y: &Node, path: &str, config: &StructureConfig) -> Option<String> {
+    let (Node::Element { name, attributes, children }, Node::Element { name: other, attributes: others, children: other_children }) = (x, y) else {
+        return None;
+    };
+    if name != other {
+        return Some(format!("{}: expected <{}>, found <{}>", path, name, other));
+    }
+    for (key, value) in attributes {
+        match others.iter().find(|(k, _)| k == key) {
+            Some((_, found)) => if compare(&scalar(value), &scalar(found), "", config).is_some() {
+                return Some(format!("{}/@{}: expected {:?}, found {:?}", path, key, value, found));
+            },
+            None => return Some(format!("{}/@{}: missing attribute", path, key)),
+        }
+    }
+    if let Some((key, _)) = others.iter().find(|(k, _)| !attributes.iter().any(|(key, _)| key == k)) {
+        return Some(format!("{}/@{}: extra attribute", path, key));
+    }
+    let mut paths = child_paths(children, path);
+    paths.extend(child_paths(other_children, path).into_iter().skip(children.len()));
+    compare_children(children, other_children, paths.into_iter(), path, "child node", config)
+}
+
+fn compare(x: &Node, y: &Node, path: &str, config: &StructureConfig) -> Option<String> {
+    match (x, y) {
+        (Node::Null, Node::Null) => None,
+        (Node::Bool(a), Node::Bool(b)) if a == b => None,
+        (Node::Number(a), Node::Number(b)) if numbers_equal(a, b, config) => None,
+        (Node::Text(a), Node::Text(b)) if a == b => None,
+        (Node::List(a), Node::List(b)) => compare_lists(a, b, path, config),
+        (Node::Map(a), Node::Map(b)) => compare_maps(a, b, path, config),
+        (Node::Element { .. }, Node::Element { .. }) => compare_elements(x, y, path, config),
+        _ => Some(format!("{}: expected {}, found {}", path, x.describe(), y.describe())),
+    }
+}
+
+/// Parses the answer and the output as JSON, CSV or XML and reports the path
+/// of the first difference. Output that does not parse is WA; an answer
+/// that does not parse is an error of the problem.
+pub fn judge_structured(ans: &str, output: &str, config: &CompareConfig) -> Result<CompareResult, String> {
+    let expected = parse(ans, config)?;
+    let info = match parse(output, config) {
+        Ok(found) => {
+            let root = match &expected {
+                Node::Element { name, .. } => format!("/{}", name),
+                _ => "$".to_string(),
+            };
+            compare(&expected, &found, &root, &config.structure)
+        },
+        Err(e) => Some(format!("output is {}", e)),
+    };
+    Ok(CompareResult {
+        status: if info.is_some() { BaseStatus::WA } else { BaseStatus::AC },
+        changeset: None,
+        info,
+        score: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: CompareMode, structure: StructureConfig) -> CompareConfig {
+        CompareConfig { mode, structure, ..CompareConfig::default() }
+    }
+
+    fn info(ans: &str, output: &str, config: &CompareConfig) -> Option<String> {
+        judge_structured(ans, output, config).ok().unwrap().info
+    }
+
+    #[test]
+    fn json_key_order_only_matters_when_asked() {
+        let unordered = config(CompareMode::Json, StructureConfig::default());
+        assert_eq!(info(r#"{"a": 1, "b": [true, null]}"#, r#"{"b":[true,null],"a":1}"#, &unordered), None);
+        let ordered = config(CompareMode::Json, StructureConfig { ordered_keys: true, ..StructureConfig::default() });
+        assert_eq!(
+            info(r#"{"a": 1, "b": 2}"#, r#"{"b": 2, "a": 1}"#, &ordered).as_deref(),
+            Some("$: expected key \"a\" at position 0, found \"b\"")
+        );
+    }
+
+    #[test]
+    fn json_differences_name_their_path() {
+        let config = config(CompareMode::Json, StructureConfig::default());
+        let ans = r#"{"a": {"b c": [1, "x"]}}"#;
+        assert_eq!(info(ans, r#"{"a": {"b c": [1, "y"]}}"#, &config).as_deref(), Some("$.a[\"b c\"][1]: expected \"x\", found \"y\""));
+        assert_eq!(info(ans, r#"{"a": {"b c": [1]}}"#, &config).as_deref(), Some("$.a[\"b c\"]: expected 2 element(s), found 1"));
+        assert_eq!(info(ans, r#"{"a": {}}"#, &config).as_deref(), Some("$.a: missing key \"b c\""));
+        assert_eq!(info(r#"{}"#, r#"{"z": 0}"#, &config).as_deref(), Some("$: extra key \"z\""));
+        assert_eq!(info("1", "\"1\"", &config).as_deref(), Some("$: expected 1, found \"1\""));
+    }
+
+    #[test]
+    fn numbers_by_value_with_tolerance() {
+        let exact = config(CompareMode::Json, StructureConfig::default());
+        assert_eq!(info("[1.50, 1e2]", "[1.5, 100]", &exact), None);
+        assert!(info("[0.1]", "[0.1000001]", &exact).is_some());
+        let tolerant = config(CompareMode::Json, StructureConfig { tolerance: 1e-6, ..StructureConfig::default() });
+        assert_eq!(info("[0.1, 1000000]", "[0.1000001, 1000000.5]", &tolerant), None);
+        assert!(info("[0.1]", "[0.100002]", &tolerant).is_some());
+        let textual = config(CompareMode::Json, StructureConfig { numbers_by_value: false, ..StructureConfig::default() });
+        assert_eq!(info("[1.50]", "[1.5]", &textual).as_deref(), Some("$[0]: expected 1.50, found 1.5"));
+    }
+
+    #[test]
+    fn invalid_output_is_wrong_and_invalid_answer_an_error() {
+        let config = config(CompareMode::Json, StructureConfig::default());
+        let result = judge_structured("[1]", "[1,", &config).ok().unwrap();
+        assert_eq!(result.status, BaseStatus::WA);
+        assert!(result.info.unwrap().starts_with("output is invalid JSON"));
+        assert!(judge_structured("[1,", "[1]", &config).is_err());
+    }
+
+    #[test]
+    fn csv_rows_and_headers() {
+        let plain = config(CompareMode::Csv, StructureConfig::default());
+        assert_eq!(info("a, 1.0\nb,2\n", "a,1\r\nb , 2", &plain), None);
+        assert_eq!(info("a,1\nb,2\n", "a,1\n", &plain).as_deref(), Some("$: expected 2 element(s), found 1"));
+        let header = config(CompareMode::Csv, StructureConfig { csv_header: true, ..StructureConfig::default() });
+        assert_eq!(info("name,score\nx,1\n", "name,score\nx,2\n", &header).as_deref(), Some("$[0].score: expected 1, found 2"));
+    }
+
+    #[test]
+    fn xml_elements_attributes_and_text() {
+        let config = config(CompareMode::Xml, StructureConfig::default());
+        let ans = "<r a=\"1\"><x>1.0</x><x>b</x></r>";
+        assert_eq!(info(ans, "<r a=\"1.00\">\n  <x>1</x>\n  <x>b</x>\n</r>", &config), None);
+        assert_eq!(info(ans, "<r a=\"1\"><x>1</x><x>c</x></r>", &config).as_deref(), Some("/r/x[2]/text()[1]: expected \"b\", found \"c\""));
+        assert_eq!(info(ans, "<r><x>1</x><x>b</x></r>", &config).as_deref(), Some("/r/@a: missing attribute"));
+        assert_eq!(info(ans, "<r a=\"1\" b=\"2\"><x>1</x><x>b</x></r>", &config).as_deref(), Some("/r/@b: extra attribute"));
+        assert_eq!(info(ans, "<r a=\"1\"><x>1</x><y>b</y></r>", &config).as_deref(), Some("/r/x[2]: expected <x>, found <y>"));
+    }
+}
@@ -1,6 +1,12 @@
+use std::str::FromStr;
 use difference::Changeset;
-use crate::compare::compare::CompareConfig;
+use crate::compare::compare::{CompareConfig, CompareMode};
+use crate::compare::normalize::{Normalize, UnicodeForm};
+use crate::compare::render::RenderConfig;
+use crate::compare::structured::StructureConfig;
 use crate::compare::results::{BaseResult, BaseStatus};
+use crate::compare::score::ScoreUnit;
+use crate::error::Error;
 
 impl Default for CompareConfig {
     fn default() -> Self {
@@ -9,6 +15,86 @@ impl Default for CompareConfig {
             ignore_end_of_line_space: true,
             fast_compare: false,
             force_fast_compare: 30000,
+            mode: CompareMode::Lines,
+            normalize: Normalize {
+                line_endings: true,
+                bom: true,
+                ..Normalize::default()
+            },
+            presentation: None,
+            structure: StructureConfig::default(),
+            score: None,
+        }
+    }
+}
+
+impl FromStr for CompareMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(CompareMode::Lines),
+            "tokens" => Ok(CompareMode::Tokens),
+            "binary" => Ok(CompareMode::Binary),
+            "unordered_lines" => Ok(CompareMode::UnorderedLines),
+            "unordered_tokens" => Ok(CompareMode::UnorderedTokens),
+            "sorted" => Ok(CompareMode::Sorted),
+            "regex" => Ok(CompareMode::Regex),
+            "glob" => Ok(CompareMode::Glob),
+            "json" => Ok(CompareMode::Json),
+            "csv" => Ok(CompareMode::Csv),
+            "xml" => Ok(CompareMode::Xml),
+            _ => Err(Error::config("mode", format!("unknown compare mode {:?}", s))),
+        }
+    }
+}
+
+impl FromStr for ScoreUnit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(ScoreUnit::Lines),
+            "tokens" => Ok(ScoreUnit::Tokens),
+            _ => Err(Error::config("score", format!("unknown score unit {:?}", s))),
+        }
+    }
+}
+
+impl FromStr for UnicodeForm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nfc" => Ok(UnicodeForm::Nfc),
+            "nfd" => Ok(UnicodeForm::Nfd),
+            "nfkc" => Ok(UnicodeForm::Nfkc),
+            "nfkd" => Ok(UnicodeForm::Nfkd),
+            _ => Err(Error::config("unicode", format!("unknown unicode form {:?}", s))),
+        }
+    }
+}
+
+impl Default for StructureConfig {
+    fn default() -> Self {
+        Self {
+            ordered_keys: false,
+            numbers_by_value: true,
+            tolerance: 0.0,
+            csv_header: false,
+        }
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            context: 3,
+            max_lines: 200,
+            max_line_width: 120,
+            column_width: 40,
+            show_invisible: true,
+            color: false,
         }
     }
 }
@@ -21,6 +107,7 @@ impl Default for BaseResult {
             time: 0,
             memory: 0,
             info: None,
+            score: None,
         }
     }
 }
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use difference::Changeset;
+use crate::compare::compare::{CompareConfig, CompareMode};
+use crate::compare::normalize::normalize;
+use crate::compare::results::{BaseStatus, CompareResult};
+
+/// How many missing or extra items are listed in `info`.
+const LISTED: usize = 5;
+
+/// Items of `ans` not matched in `output` and the other way round, each in
+/// the order they appear, with duplicates counted.
+fn difference<'a>(ans: &[&'a str], output: &[&'a str]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut counts: HashMap<&str, i64> = HashMap::new();
+    ans.iter().for_each(|x| *counts.entry(x).or_insert(0) += 1);
+    output.iter().for_each(|x| *counts.entry(x).or_insert(0) -= 1);
+    let mut missing = vec![];
+    for x in ans {
+        let count = counts.get_mut(x).unwrap();
+        if *count > 0 {
+            missing.push(*x);
+            *count -= 1;
+        }
+    }
+    let mut extra = vec![];
+    for x in output {
+        let count = counts.get_mut(x).unwrap();
+        if *count < 0 {
+            extra.push(*x);
+            *count += 1;
+        }
+    }
+    (missing, extra)
+}
+
+fn describe(kind: &str, what: &str, items: &[&str]) -> Option<String> {
+    if items.is_empty() {
+        return None;
+    }
+    let mut listed = items.iter().take(LISTED).map(|x| format!("{:?}", x)).collect::<Vec<String>>();
+    if items.len() > LISTED {
+        listed.push("...".to_string());
+    }
+    Some(format!("{} {} {}(s): {}", kind, items.len(), what, listed.join(", ")))
+}
+
+fn report(missing: &[&str], extra: &[&str], what: &str) -> Option<String> {
+    let parts = [describe("missing", what, missing), describe("extra", what, extra)];
+    let parts = parts.into_iter().flatten().collect::<Vec<String>>();
+    if parts.is_empty() { None } else { Some(parts.join("; ")) }
+}
+
+/// Compares in one of the order-insensitive modes. The answer and output
+/// are normalized first, and whatever is missing or extra ends up in
+/// `info`. `Sorted` also keeps the diff of the sorted lines.
+pub fn judge_unordered(ans: &str, output: &str, config: &CompareConfig) -> CompareResult {
+    let ans = normalize(ans, config);
+    let output = normalize(output, config);
+    let mut ans_lines = ans.split('\n').collect::<Vec<&str>>();
+    let mut output_lines = output.split('\n').collect::<Vec<&str>>();
+    let mut changeset = None;
+    let info = match config.mode {
+        CompareMode::UnorderedTokens if ans_lines.len() != output_lines.len() => Some(format!(
+            "expected {} line(s), found {}", ans_lines.len(), output_lines.len()
+        )),
+        CompareMode::UnorderedTokens => ans_lines.iter().zip(&output_lines).enumerate().find_map(|(index, (x, y))| {
+            let x = x.split_ascii_whitespace().collect::<Vec<&str>>();
+            let y = y.split_ascii_whitespace().collect::<Vec<&str>>();
+            let (missing, extra) = difference(&x, &y);
+            report(&missing, &extra, "token").map(|info| format!("line {}: {}", index + 1, info))
+        }),
+        _ => {
+            if config.mode == CompareMode::Sorted {
+                ans_lines.sort_unstable();
+                output_lines.sort_unstable();
+                changeset = Some(Changeset::new(&ans_lines.join("\n"), &output_lines.join("\n"), "\n"));
+            }
+            let (missing, extra) = difference(&ans_lines, &output_lines);
+            report(&missing, &extra, "line")
+        },
+    };
+    CompareResult {
+        status: if info.is_some() { BaseStatus::WA } else { BaseStatus::AC },
+        changeset,
+        info,
+        score: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn judge(ans: &str, output: &str, mode: CompareMode) -> CompareResult {
+        judge_unordered(ans, output, &CompareConfig { mode, ..CompareConfig::default() })
+    }
+
+    #[test]
+    fn difference_counts_duplicates() {
+        assert_eq!(difference(&["a", "b", "a"], &["b", "a"]), (vec!["a"], vec![]));
+        assert_eq!(difference(&["a"], &["b", "a", "b"]), (vec![], vec!["b", "b"]));
+        assert_eq!(difference(&["a", "b"], &["b", "a"]), (vec![], vec![]));
+    }
+
+    #[test]
+    fn lines_in_any_order() {
+        let result = judge("1\n2\n3\n", "3\r\n1\n2\n", CompareMode::UnorderedLines);
+        assert_eq!((result.status, result.info), (BaseStatus::AC, None));
+        let result = judge("1\n2\n2\n", "2\n1\n1\n", CompareMode::UnorderedLines);
+        assert_eq!(result.status, BaseStatus::WA);
+        assert_eq!(result.info.as_deref(), Some("missing 1 line(s): \"2\"; extra 1 line(s): \"1\""));
+        assert!(result.changeset.is_none());
+    }
+
+    #[test]
+    fn only_a_few_items_are_listed() {
+        let output = (0..8).map(|x| x.to_string()).collect::<Vec<_>>().join("\n");
+        let result = judge("", &output, CompareMode::UnorderedLines);
+        assert_eq!(
+            result.info.as_deref(),
+            Some("missing 1 line(s): \"\"; extra 8 line(s): \"0\", \"1\", \"2\", \"3\", \"4\", ...")
+        );
+    }
+
+    #[test]
+    fn tokens_in_any_order_within_each_line() {
+        assert_eq!(judge("1 2 3\n4 5\n", "3  1 2\n5 4\n", CompareMode::UnorderedTokens).status, BaseStatus::AC);
+        let result = judge("1 2\n3 4\n", "4 3\n2 1\n", CompareMode::UnorderedTokens);
+        assert_eq!(result.info.as_deref(), Some("line 1: missing 2 token(s): \"1\", \"2\"; extra 2 token(s): \"4\", \"3\""));
+        let result = judge("1 2\n3 4\n", "1 2 3 4\n", CompareMode::UnorderedTokens);
+        assert_eq!(result.info.as_deref(), Some("expected 2 line(s), found 1"));
+    }
+
+    #[test]
+    fn sorted_keeps_the_diff_of_the_sorted_lines() {
+        let result = judge("b\na\n", "a\nb\n", CompareMode::Sorted);
+        assert_eq!(result.status, BaseStatus::AC);
+        assert_eq!(result.changeset.unwrap().distance, 0);
+        let result = judge("b\na\n", "c\na\n", CompareMode::Sorted);
+        assert_eq!(result.status, BaseStatus::WA);
+        assert!(result.changeset.unwrap().distance > 0);
+    }
+}
@@ -0,0 +1,378 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use crate::dir_utils::traits::BaseDir;
+use crate::error::Error;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+/// Limits applied while extracting, so that a small archive cannot expand
+/// into an unbounded amount of data.
+#[derive(Clone)]
+pub struct ArchiveConfig {
+    pub max_size: u64,
+    pub max_entries: usize,
+    pub allow_symlinks: bool,
+}
+
+impl ArchiveFormat {
+    /// Detects the format from the magic bytes of the file.
+    pub fn detect(path: &Path) -> Result<Self, Error> {
+        let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+        let mut header = vec![0u8; 262];
+        let mut len = 0;
+        while len < header.len() {
+            match file.read(&mut header[len..]).map_err(|e| Error::io(path, e))? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        let header = &header[..len];
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            return Ok(ArchiveFormat::Zip);
+        }
+        if header.starts_with(&[0x1f, 0x8b]) {
+            return Ok(ArchiveFormat::TarGz);
+        }
+        if header.len() >= 262 && &header[257..262] == b"ustar" {
+            return Ok(ArchiveFormat::Tar);
+        }
+        Err(Error::Archive { path: path.into(), reason: "unknown archive format".to_string() })
+    }
+}
+
+struct Extractor<'a> {
+    archive: &'a Path,
+    root: PathBuf,
+    config: &'a ArchiveConfig,
+    entries: usize,
+    size: u64,
+}
+
+impl<'a> Extractor<'a> {
+    fn error(&self, reason: String) -> Error {
+        Error::Archive { path: self.archive.into(), reason }
+    }
+
+    /// Turns an entry name into a path relative to the root, refusing
+    /// absolute paths and `..` components.
+    fn sanitize(&self, name: &Path) -> Result<PathBuf, Error> {
+        let mut result = PathBuf::new();
+        for component in name.components() {
+            match component {
+                Component::Normal(x) => result.push(x),
+                Component::CurDir => (),
+                _ => return Err(self.error(format!("path escapes the directory: {}", name.display()))),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Counts the entry and makes sure none of the parents of `relative` is
+    /// a symlink, so nothing is written through a link created earlier.
+    fn prepare(&mut self, relative: &Path) -> Result<PathBuf, Error> {
+        self.entries += 1;
+        if self.entries > self.config.max_entries {
+            return Err(self.error(format!("more than {} entries", self.config.max_entries)));
+        }
+        let mut path = self.root.clone();
+        for component in relative.components() {
+            path.push(component);
+            if let Ok(metadata) = fs::symlink_metadata(&path) {
+                if metadata.file_type().is_symlink() {
+                    return Err(self.error(format!("entry goes through a symlink: {}", relative.display())));
+                }
+            }
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        Ok(path)
+    }
+
+    fn dir(&mut self, relative: &Path) -> Result<(), Error> {
+        let path = self.prepare(relative)?;
+        fs::create_dir_all(&path).map_err(|e| Error::io(&path, e))
+    }
+
+    /// Writes a regular file, counting the bytes actually decompressed rather
+    /// than trusting the sizes stored in the archive.
+    fn file(&mut self, relative: &Path, reader: &mut dyn Read, mode: u32) -> Result<(), Error> {
+        let path = self.prepare(relative)?;
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true)
+            .open(&path).map_err(|e| Error::io(&path, e))?;
+        let remaining = self.config.max_size - self.size;
+        let written = std::io::copy(&mut reader.take(remaining + 1), &mut file)
+            .map_err(|e| Error::io(&path, e))?;
+        if written > remaining {
+            let _ = fs::remove_file(&path);
+            return Err(self.error(format!("content exceeds {} bytes", self.config.max_size)));
+        }
+        self.size += written;
+        let mode = 0o644 | (mode & 0o111);
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).map_err(|e| Error::io(&path, e))
+    }
+
+    /// Creates a symlink whose target stays inside the root.
+    fn symlink(&mut self, relative: &Path, target: &Path) -> Result<(), Error> {
+        if !self.config.allow_symlinks {
+            return Err(self.error(format!("symlinks are not allowed: {}", relative.display())));
+        }
+        let mut resolved = relative.parent().map(|x| x.to_path_buf()).unwrap_or_default();
+        for component in target.components() {
+            let escaped = match component {
+                Component::Normal(x) => { resolved.push(x); false },
+                Component::CurDir => false,
+                Component::ParentDir => !resolved.pop(),
+                _ => true,
+            };
+            if escaped {
+                return Err(self.error(format!("symlink escapes the directory: {} -> {}", relative.display(), target.display())));
+            }
+        }
+        let path = self.prepare(relative)?;
+        if !self.resolves_inside(&path, target)? {
+            return Err(self.error(format!("symlink escapes the directory: {} -> {}", relative.display(), target.display())));
+        }
+        symlink(target, &path).map_err(|e| Error::io(&path, e))
+    }
+
+    /// Checks that `target`, taken from the directory of the link at `path`,
+    /// stays inside the root once the links extracted so far are followed.
+    /// Only the longest existing part is resolved; the rest may only hold
+    /// plain names, since a `..` after a missing name cannot be resolved yet.
+    fn resolves_inside(&self, path: &Path, target: &Path) -> Result<bool, Error> {
+        let root = self.root.canonicalize().map_err(|e| Error::io(&self.root, e))?;
+        let full = path.parent().unwrap_or(&self.root).join(target);
+        for existing in full.ancestors() {
+            if let Ok(real) = existing.canonicalize() {
+                let rest = full.strip_prefix(existing).unwrap_or(&full);
+                return Ok(real.starts_with(&root) && rest.components().all(|x| matches!(x, Component::Normal(_))));
+            }
+        }
+        Ok(false)
+    }
+}
+
+fn extract_tar(reader: impl Read, extractor: &mut Extractor) -> Result<(), Error> {
+    let mut archive = tar::Archive::new(reader);
+    let entries = archive.entries().map_err(|e| extractor.error(e.to_string()))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| extractor.error(e.to_string()))?;
+        let name = entry.path().map_err(|e| extractor.error(e.to_string()))?.to_path_buf();
+        let relative = extractor.sanitize(&name)?;
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let kind = entry.header().entry_type();
+        if kind.is_dir() {
+            extractor.dir(&relative)?;
+        } else if kind.is_file() {
+            let mode = entry.header().mode().unwrap_or(0o644);
+            extractor.file(&relative, &mut entry, mode)?;
+        } else if kind.is_symlink() {
+            let target = entry.link_name().map_err(|e| extractor.error(e.to_string()))?
+                .ok_or_else(|| extractor.error(format!("symlink without target: {}", name.display())))?
+                .to_path_buf();
+            extractor.symlink(&relative, &target)?;
+        } else {
+            return Err(extractor.error(format!("unsupported entry type {:?}: {}", kind, name.display())));
+        }
+    }
+    Ok(())
+}
+
+fn extract_zip(file: File, extractor: &mut Extractor) -> Result<(), Error> {
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| extractor.error(e.to_string()))?;
+    if archive.len() > extractor.config.max_entries {
+        return Err(extractor.error(format!("more than {} entries", extractor.config.max_entries)));
+    }
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(|e| extractor.error(e.to_string()))?;
+        let name = PathBuf::from(entry.name().map_err(|e| extractor.error(e.to_string()))?.to_string());
+        let relative = extractor.sanitize(&name)?;
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        if entry.is_dir() {
+            extractor.dir(&relative)?;
+        } else if entry.is_symlink() {
+            let mut target = String::new();
+            entry.by_ref().take(4096).read_to_string(&mut target)
+                .map_err(|e| extractor.error(e.to_string()))?;
+            extractor.symlink(&relative, Path::new(&target))?;
+        } else {
+            let mode = entry.unix_mode().unwrap_or(0o644);
+            extractor.file(&relative, &mut entry, mode)?;
+        }
+    }
+    Ok(())
+}
+
+/// Extracts a tar, tar.gz or zip archive into `dir`. Returns the number of
+/// entries written.
+pub fn extract(archive: &Path, dir: &dyn BaseDir, config: &ArchiveConfig) -> Result<usize, Error> {
+    let format = ArchiveFormat::detect(archive)?;
+    let file = File::open(archive).map_err(|e| Error::io(archive, e))?;
+    let mut extractor = Extractor {
+        archive,
+        root: PathBuf::from(dir.get_src()),
+        config,
+        entries: 0,
+        size: 0,
+    };
+    match format {
+        ArchiveFormat::Tar => extract_tar(file, &mut extractor)?,
+        ArchiveFormat::TarGz => extract_tar(GzDecoder::new(file), &mut extractor)?,
+        ArchiveFormat::Zip => extract_zip(file, &mut extractor)?,
+    }
+    Ok(extractor.entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use crate::dir_utils::guard::DirGuard;
+    use crate::dir_utils::local::{Local, LocalConfig};
+    use crate::testing;
+
+    fn scratch(name: &str) -> DirGuard<Local> {
+        let dir = testing::scratch(name);
+        fs::create_dir_all(dir.src.join("out")).unwrap();
+        dir
+    }
+
+    fn tar_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (name, data) in files {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn extract_to(path: &Path, archive: &Path, config: &ArchiveConfig) -> Result<usize, Error> {
+        let dir = Local::from(&LocalConfig::new(path.join("out").to_string_lossy().to_string()));
+        extract(archive, &dir, config)
+    }
+
+    #[test]
+    fn detects_formats_by_magic_bytes() {
+        let tmp = scratch("detect");
+        let path = &tmp.src;
+        let tar = tar_of(&[("1.in", b"1\n")]);
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(&tar).unwrap();
+        fs::write(path.join("a.zip"), b"PK\x03\x04rest").unwrap();
+        fs::write(path.join("a.bin"), gz.finish().unwrap()).unwrap();
+        fs::write(path.join("a"), &tar).unwrap();
+        fs::write(path.join("a.txt"), b"plain text").unwrap();
+        assert_eq!(ArchiveFormat::detect(&path.join("a.zip")).unwrap(), ArchiveFormat::Zip);
+        assert_eq!(ArchiveFormat::detect(&path.join("a.bin")).unwrap(), ArchiveFormat::TarGz);
+        assert_eq!(ArchiveFormat::detect(&path.join("a")).unwrap(), ArchiveFormat::Tar);
+        assert!(matches!(ArchiveFormat::detect(&path.join("a.txt")), Err(Error::Archive { .. })));
+        assert!(matches!(ArchiveFormat::detect(&path.join("none")), Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn extracts_tar_gz_with_sanitized_modes() {
+        let tmp = scratch("targz");
+        let path = &tmp.src;
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(&tar_of(&[("./1.in", b"1 2\n"), ("sub/1.ans", b"3\n")])).unwrap();
+        fs::write(path.join("a.tgz"), gz.finish().unwrap()).unwrap();
+        assert_eq!(extract_to(path, &path.join("a.tgz"), &ArchiveConfig::default()).unwrap(), 2);
+        assert_eq!(fs::read(path.join("out/1.in")).unwrap(), b"1 2\n");
+        assert_eq!(fs::read(path.join("out/sub/1.ans")).unwrap(), b"3\n");
+        let mode = fs::metadata(path.join("out/1.in")).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode, 0o755);
+    }
+
+    #[test]
+    fn extracts_zip() {
+        let tmp = scratch("zip");
+        let path = &tmp.src;
+        let mut zip = zip::ZipWriter::new(File::create(path.join("a.zip")).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        zip.add_directory("sub/", options).unwrap();
+        zip.start_file("sub/1.in", options).unwrap();
+        zip.write_all(b"1\n").unwrap();
+        zip.finish().unwrap();
+        assert_eq!(extract_to(path, &path.join("a.zip"), &ArchiveConfig::default()).unwrap(), 2);
+        assert_eq!(fs::read(path.join("out/sub/1.in")).unwrap(), b"1\n");
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let tmp = scratch("limits");
+        let path = &tmp.src;
+        fs::write(path.join("a.tar"), tar_of(&[("1.in", b"12345"), ("2.in", b"67890")])).unwrap();
+        let config = ArchiveConfig { max_size: 9, ..ArchiveConfig::default() };
+        assert!(matches!(extract_to(path, &path.join("a.tar"), &config), Err(Error::Archive { .. })));
+        assert!(!path.join("out/2.in").exists());
+        let config = ArchiveConfig { max_entries: 1, ..ArchiveConfig::default() };
+        assert!(matches!(extract_to(path, &path.join("a.tar"), &config), Err(Error::Archive { .. })));
+        let config = ArchiveConfig { max_size: 10, max_entries: 2, ..ArchiveConfig::default() };
+        assert_eq!(extract_to(path, &path.join("a.tar"), &config).unwrap(), 2);
+    }
+
+    #[test]
+    fn escaping_paths_and_symlinks_are_refused() {
+        let tmp = scratch("escape");
+        let path = &tmp.src;
+        let config = ArchiveConfig { allow_symlinks: true, ..ArchiveConfig::default() };
+        let mut extractor = Extractor {
+            archive: Path::new("a.tar"),
+            root: path.join("out"),
+            config: &config,
+            entries: 0,
+            size: 0,
+        };
+        assert_eq!(extractor.sanitize(Path::new("./a/./b")).unwrap(), PathBuf::from("a/b"));
+        assert!(extractor.sanitize(Path::new("../a")).is_err());
+        assert!(extractor.sanitize(Path::new("a/../../b")).is_err());
+        assert!(extractor.sanitize(Path::new("/etc/passwd")).is_err());
+        assert!(extractor.symlink(Path::new("a/link"), Path::new("../../b")).is_err());
+        assert!(extractor.symlink(Path::new("a/link"), Path::new("/etc/passwd")).is_err());
+        extractor.symlink(Path::new("a/link"), Path::new("../b")).unwrap();
+        assert!(extractor.file(Path::new("a/link/c"), &mut &b"c"[..], 0o644).is_err());
+        let config = ArchiveConfig::default();
+        extractor.config = &config;
+        assert!(extractor.symlink(Path::new("d"), Path::new("b")).is_err());
+    }
+
+    #[test]
+    fn chained_symlinks_cannot_escape() {
+        let tmp = scratch("chain");
+        let config = ArchiveConfig { allow_symlinks: true, ..ArchiveConfig::default() };
+        let mut extractor = Extractor {
+            archive: Path::new("a.tar"),
+            root: tmp.src.join("out"),
+            config: &config,
+            entries: 0,
+            size: 0,
+        };
+        extractor.symlink(Path::new("a/up"), Path::new("..")).unwrap();
+        assert!(extractor.symlink(Path::new("b"), Path::new("a/up/..")).is_err());
+        assert!(extractor.symlink(Path::new("c"), Path::new("a/up/../../x")).is_err());
+        assert!(extractor.symlink(Path::new("d"), Path::new("missing/..")).is_err());
+        extractor.symlink(Path::new("e"), Path::new("a/up/a/f")).unwrap();
+        assert!(!tmp.src.join("out/b").exists() && !tmp.src.join("out/c").exists());
+    }
+}
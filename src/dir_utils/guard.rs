@@ -0,0 +1,214 @@
+use std::fs;
+use std::ffi::CString;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+use libc::{umount2, MNT_DETACH};
+use crate::dir_utils::tmpfs::{DiskUsage, KEEP_MARKER, MOUNT_SOURCE_PREFIX};
+use crate::dir_utils::traits::BaseDir;
+use crate::error::Error;
+
+/// Owns a `BaseDir` and cleans it up when dropped, so that a panic or an
+/// early return does not leave mounts and directories behind.
+pub struct DirGuard<T: BaseDir> {
+    pub inner: T,
+    pub keep: bool,
+}
+
+impl<T: BaseDir> DirGuard<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner, keep: false }
+    }
+
+    /// Keeps the directory around after the guard is dropped, e.g. to
+    /// inspect the workspace of a failed run.
+    pub fn keep(&mut self, keep: bool) {
+        self.keep = keep;
+    }
+}
+
+impl<T: BaseDir> Deref for DirGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: BaseDir> DerefMut for DirGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: BaseDir> BaseDir for DirGuard<T> {
+    fn create(&mut self) -> Result<(), Error> {
+        self.inner.create()
+    }
+
+    fn clean(&mut self) -> Result<(), Error> {
+        self.inner.clean()
+    }
+
+    fn get_src(&self) -> String {
+        self.inner.get_src()
+    }
+
+    fn read_only(&self) -> bool {
+        self.inner.read_only()
+    }
+
+    fn get_bind_string(&mut self, target: &String) -> String {
+        self.inner.get_bind_string(target)
+    }
+
+    fn usage(&mut self) -> Option<DiskUsage> {
+        self.inner.usage()
+    }
+
+    fn reset_usage(&mut self) {
+        self.inner.reset_usage()
+    }
+
+    fn mark_kept(&mut self) {
+        self.inner.mark_kept()
+    }
+}
+
+impl<T: BaseDir> Drop for DirGuard<T> {
+    fn drop(&mut self) {
+        if self.keep {
+            self.inner.mark_kept();
+        } else {
+            let _ = self.inner.clean();
+        }
+    }
+}
+
+/// Decodes the octal escapes (`\040` for a space, ...) used in /proc/mounts.
+fn unescape_mount_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+            let digits = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or("");
+            if let Ok(x) = u8::from_str_radix(digits, 8) {
+                result.push(x);
+                i += 4;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+/// Unmounts and removes the tmpfs workspaces left behind by judger processes
+/// that are no longer alive, except the ones marked with `KEEP_MARKER`.
+/// Returns the paths that were removed and the errors met on the way; a
+/// failure does not stop the sweep.
+pub fn sweep_stale_mounts() -> (Vec<String>, Vec<Error>) {
+    let mut removed = vec![];
+    let mut errors = vec![];
+    let mounts = match fs::read_to_string("/proc/self/mounts") {
+        Ok(x) => x,
+        Err(e) => return (removed, vec![Error::io("/proc/self/mounts", e)]),
+    };
+    for line in mounts.lines() {
+        let fields = line.split(' ').collect::<Vec<&str>>();
+        if fields.len() < 3 || fields[2] != "tmpfs" {
+            continue;
+        }
+        let pid = match fields[0].strip_prefix(MOUNT_SOURCE_PREFIX) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        if pid.parse::<u32>().is_err() || Path::new(&format!("/proc/{}", pid)).exists() {
+            continue;
+        }
+        let path = unescape_mount_path(fields[1]);
+        if Path::new(&path).join(KEEP_MARKER).exists() {
+            continue;
+        }
+        unsafe {
+            if umount2(CString::new(path.as_bytes()).unwrap().as_ptr(), MNT_DETACH) == -1 {
+                errors.push(Error::mount(&path));
+                continue;
+            }
+        }
+        if let Err(e) = fs::remove_dir_all(&path) {
+            errors.push(Error::io(&path, e));
+            continue;
+        }
+        removed.push(path);
+    }
+    (removed, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records which of `clean` and `mark_kept` were called.
+    struct Probe {
+        calls: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl BaseDir for Probe {
+        fn create(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn clean(&mut self) -> Result<(), Error> {
+            self.calls.borrow_mut().push("clean");
+            Ok(())
+        }
+
+        fn get_src(&self) -> String {
+            "/probe".to_string()
+        }
+
+        fn read_only(&self) -> bool {
+            false
+        }
+
+        fn get_bind_string(&mut self, target: &String) -> String {
+            target.clone()
+        }
+
+        fn mark_kept(&mut self) {
+            self.calls.borrow_mut().push("mark_kept");
+        }
+    }
+
+    #[test]
+    fn drop_cleans_unless_kept() {
+        let calls = Rc::new(RefCell::new(vec![]));
+        drop(DirGuard::new(Probe { calls: calls.clone() }));
+        assert_eq!(*calls.borrow(), vec!["clean"]);
+
+        let calls = Rc::new(RefCell::new(vec![]));
+        let mut guard = DirGuard::new(Probe { calls: calls.clone() });
+        guard.keep(true);
+        drop(guard);
+        assert_eq!(*calls.borrow(), vec!["mark_kept"]);
+    }
+
+    #[test]
+    fn unescapes_octal_sequences() {
+        assert_eq!(unescape_mount_path("/tmp/a\\040b"), "/tmp/a b");
+        assert_eq!(unescape_mount_path("/tmp/tab\\011x\\134y"), "/tmp/tab\tx\\y");
+        assert_eq!(unescape_mount_path("/tmp/plain"), "/tmp/plain");
+    }
+
+    #[test]
+    fn keeps_invalid_or_short_escapes() {
+        assert_eq!(unescape_mount_path("/tmp/a\\09x"), "/tmp/a\\09x");
+        assert_eq!(unescape_mount_path("/tmp/end\\04"), "/tmp/end\\04");
+        assert_eq!(unescape_mount_path("\\"), "\\");
+    }
+}
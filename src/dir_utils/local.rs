@@ -1,9 +1,9 @@
 use std::fs;
 use std::fs::Permissions;
 use std::path::PathBuf;
-use errno::errno;
 
 use crate::dir_utils::traits::BaseDir;
+use crate::error::Error;
 
 #[derive(Clone)]
 pub struct LocalConfig {
@@ -25,22 +25,22 @@ pub struct Local {
 }
 
 impl BaseDir for Local {
-    fn create(&mut self) -> Result<(), String> {
+    fn create(&mut self) -> Result<(), Error> {
         if self.created == true {
             return Ok(());
         }
         if !self.src.is_dir() {
             if self.config.create_if_not_exist {
                 if self.config.create_recursively {
-                    if fs::create_dir_all(&self.src).is_err() { return Err(errno().to_string()); }
+                    if let Err(e) = fs::create_dir_all(&self.src) { return Err(Error::io(&self.src, e)); }
                 } else {
-                    if fs::create_dir(&self.src).is_err() { return Err(errno().to_string()); }
+                    if let Err(e) = fs::create_dir(&self.src) { return Err(Error::io(&self.src, e)); }
                 }
             }
             match &self.config.permissions {
                 Some(permissions) => {
-                    if fs::set_permissions(&self.src, permissions.clone()).is_err() {
-                        return Err(errno().to_string());
+                    if let Err(e) = fs::set_permissions(&self.src, permissions.clone()) {
+                        return Err(Error::io(&self.src, e));
                     }
                 },
                 None => ()
@@ -50,13 +50,13 @@ impl BaseDir for Local {
         Ok(())
     }
 
-    fn clean(&mut self) -> Result<(), String> {
+    fn clean(&mut self) -> Result<(), Error> {
         if self.created == false || self.config.clean == false {
             return Ok(());
         }
         match fs::remove_dir_all(&self.src) {
             Ok(_) => { self.created = false; Ok(()) },
-            Err(e) => Err(e.to_string())
+            Err(e) => Err(Error::io(&self.src, e))
         }
     }
 
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::dir_utils::traits::BaseDir;
+use crate::error::Error;
+
+/// Name of the manifest stored at the root of a problem data directory. It
+/// is left out of the manifest itself.
+pub const MANIFEST_FILE: &str = ".manifest.toml";
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    pub mode: u32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ManifestMismatch {
+    Missing(String),
+    Extra(String),
+    Changed { expected: ManifestEntry, found: ManifestEntry },
+}
+
+impl Display for ManifestMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestMismatch::Missing(path) => write!(f, "missing: {}", path),
+            ManifestMismatch::Extra(path) => write!(f, "unexpected: {}", path),
+            ManifestMismatch::Changed { expected, found } => write!(
+                f, "changed: {} (size {} -> {}, mode {:o} -> {:o}, sha256 {} -> {})",
+                expected.path, expected.size, found.size, expected.mode, found.mode,
+                expected.sha256, found.sha256
+            ),
+        }
+    }
+}
+
+fn hash_file(path: &Path) -> Result<String, Error> {
+    let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 16];
+    loop {
+        let len = file.read(&mut buffer).map_err(|e| Error::io(path, e))?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buffer[..len]);
+    }
+    Ok(hasher.finalize().iter().map(|x| format!("{:02x}", x)).collect())
+}
+
+fn walk(root: &Path, relative: &Path, entries: &mut Vec<ManifestEntry>) -> Result<(), Error> {
+    let dir = root.join(relative);
+    for entry in fs::read_dir(&dir).map_err(|e| Error::io(&dir, e))? {
+        let entry = entry.map_err(|e| Error::io(&dir, e))?;
+        let relative = relative.join(entry.file_name());
+        if relative == Path::new(MANIFEST_FILE) {
+            continue;
+        }
+        let path = entry.path();
+        let metadata = fs::symlink_metadata(&path).map_err(|e| Error::io(&path, e))?;
+        if metadata.is_dir() {
+            walk(root, &relative, entries)?;
+            continue;
+        }
+        let sha256 = if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&path).map_err(|e| Error::io(&path, e))?;
+            Sha256::digest(target.to_string_lossy().as_bytes())
+                .iter().map(|x| format!("{:02x}", x)).collect()
+        } else {
+            hash_file(&path)?
+        };
+        entries.push(ManifestEntry {
+            path: relative.to_string_lossy().to_string(),
+            size: metadata.len(),
+            sha256,
+            mode: metadata.permissions().mode() & 0o7777,
+        });
+    }
+    Ok(())
+}
+
+impl Manifest {
+    /// Records relative path, size, SHA-256 and mode of every file in `dir`.
+    pub fn compute(dir: &dyn BaseDir) -> Result<Self, Error> {
+        let mut entries = vec![];
+        walk(Path::new(&dir.get_src()), Path::new(""), &mut entries)?;
+        entries.sort_by(|x, y| x.path.cmp(&y.path));
+        Ok(Self { entries })
+    }
+
+    /// Loads the manifest stored in `dir`, if there is one.
+    pub fn load(dir: &dyn BaseDir) -> Result<Option<Self>, Error> {
+        let path = Path::new(&dir.get_src()).join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+        toml::from_str(&content).map(Some).map_err(|e| Error::config(&path, e))
+    }
+
+    pub fn store(&self, dir: &dyn BaseDir) -> Result<(), Error> {
+        let path = Path::new(&dir.get_src()).join(MANIFEST_FILE);
+        let content = toml::to_string(self).map_err(|e| Error::config(&path, e))?;
+        fs::write(&path, content).map_err(|e| Error::io(&path, e))
+    }
+
+    /// Lists every difference between the manifest and the current content
+    /// of `dir`.
+    pub fn verify(&self, dir: &dyn BaseDir) -> Result<Vec<ManifestMismatch>, Error> {
+        let current = Manifest::compute(dir)?;
+        let found = current.entries.iter().map(|x| (x.path.as_str(), x)).collect::<HashMap<_, _>>();
+        let expected = self.entries.iter().map(|x| (x.path.as_str(), x)).collect::<HashMap<_, _>>();
+        let mut mismatches = vec![];
+        for expected in &self.entries {
+            match found.get(expected.path.as_str()).copied() {
+                None => mismatches.push(ManifestMismatch::Missing(expected.path.clone())),
+                Some(found) if found != expected => mismatches.push(ManifestMismatch::Changed {
+                    expected: expected.clone(),
+                    found: found.clone(),
+                }),
+                _ => (),
+            }
+        }
+        for found in &current.entries {
+            if !expected.contains_key(found.path.as_str()) {
+                mismatches.push(ManifestMismatch::Extra(found.path.clone()));
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Like `verify`, but turns any difference into an integrity error.
+    pub fn check(&self, dir: &dyn BaseDir) -> Result<(), Error> {
+        let mismatches = self.verify(dir)?;
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        Err(Error::Integrity {
+            path: dir.get_src().into(),
+            reason: mismatches.iter().map(|x| x.to_string()).collect::<Vec<String>>().join("; "),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir_utils::guard::DirGuard;
+    use crate::dir_utils::local::Local;
+    use crate::testing;
+
+    fn scratch(name: &str) -> DirGuard<Local> {
+        let dir = testing::scratch(name);
+        fs::create_dir_all(dir.src.join("sub")).unwrap();
+        fs::write(dir.src.join("1.in"), b"1 2\n").unwrap();
+        fs::write(dir.src.join("sub/1.ans"), b"3\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn compute_lists_files_recursively_in_order() {
+        let dir = scratch("compute");
+        let manifest = Manifest::compute(&dir).unwrap();
+        let paths = manifest.entries.iter().map(|x| x.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(paths, ["1.in", "sub/1.ans"]);
+        assert_eq!(manifest.entries[0].size, 4);
+        assert_eq!(manifest.entries[0].sha256.len(), 64);
+    }
+
+    #[test]
+    fn unchanged_directory_verifies() {
+        let dir = scratch("unchanged");
+        let manifest = Manifest::compute(&dir).unwrap();
+        manifest.store(&dir).unwrap();
+        assert!(manifest.verify(&dir).unwrap().is_empty());
+        assert!(manifest.check(&dir).is_ok());
+    }
+
+    #[test]
+    fn verify_reports_missing_extra_and_changed_files() {
+        let dir = scratch("mismatch");
+        let path = &dir.src;
+        let manifest = Manifest::compute(&dir).unwrap();
+        fs::remove_file(path.join("sub/1.ans")).unwrap();
+        fs::write(path.join("2.in"), b"").unwrap();
+        fs::write(path.join("1.in"), b"1 3\n").unwrap();
+        let mismatches = manifest.verify(&dir).unwrap();
+        assert_eq!(mismatches.len(), 3);
+        assert!(matches!(&mismatches[0], ManifestMismatch::Changed { expected, found }
+            if expected.path == "1.in" && expected.size == found.size && expected.sha256 != found.sha256));
+        assert_eq!(mismatches[1], ManifestMismatch::Missing("sub/1.ans".to_string()));
+        assert_eq!(mismatches[2], ManifestMismatch::Extra("2.in".to_string()));
+        assert!(matches!(manifest.check(&dir), Err(Error::Integrity { .. })));
+    }
+
+    #[test]
+    fn mode_changes_are_detected() {
+        let dir = scratch("mode");
+        let path = &dir.src;
+        let manifest = Manifest::compute(&dir).unwrap();
+        fs::set_permissions(path.join("1.in"), fs::Permissions::from_mode(0o600)).unwrap();
+        let mismatches = manifest.verify(&dir).unwrap();
+        assert!(matches!(&mismatches[..], [ManifestMismatch::Changed { found, .. }] if found.mode == 0o600));
+    }
+
+    #[test]
+    fn stored_manifest_is_excluded_and_loads_back() {
+        let dir = scratch("store");
+        assert!(Manifest::load(&dir).unwrap().is_none());
+        let manifest = Manifest::compute(&dir).unwrap();
+        manifest.store(&dir).unwrap();
+        assert_eq!(Manifest::load(&dir).unwrap().unwrap().entries, manifest.entries);
+        assert_eq!(Manifest::compute(&dir).unwrap().entries, manifest.entries);
+    }
+}
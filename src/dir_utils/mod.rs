@@ -1,4 +1,8 @@
 pub mod tmpfs;
 pub mod traits;
 pub mod dir;
-pub mod local;
\ No newline at end of file
+pub mod local;
+pub mod guard;
+pub mod pool;
+pub mod manifest;
+pub mod archive;
\ No newline at end of file
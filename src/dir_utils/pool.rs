@@ -0,0 +1,268 @@
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use libc::statfs;
+use crate::dir_utils::tmpfs::{DiskUsage, Tmpfs, TmpfsConfig};
+use crate::dir_utils::traits::BaseDir;
+use crate::error::Error;
+
+const HEALTH_FILE: &str = ".health";
+
+#[derive(Clone)]
+pub struct TmpfsPoolConfig {
+    pub root: String,
+    pub capacity: usize,
+    pub template: TmpfsConfig,
+}
+
+struct PoolInner {
+    config: TmpfsPoolConfig,
+    idle: Mutex<Vec<Tmpfs>>,
+    counter: AtomicUsize,
+}
+
+/// A set of pre-mounted tmpfs workspaces. Workspaces are wiped instead of
+/// unmounted when they are handed back, so busy workers do not pay for a
+/// mount and an umount per submission.
+#[derive(Clone)]
+pub struct TmpfsPool {
+    inner: Arc<PoolInner>,
+}
+
+/// A workspace borrowed from a `TmpfsPool`; it goes back to the pool when
+/// dropped.
+pub struct PooledTmpfs {
+    tmpfs: Option<Tmpfs>,
+    pool: TmpfsPool,
+}
+
+/// Removes everything inside `path` but keeps `path` itself, and resets
+/// its permission bits to `mode` in case the previous task changed them.
+fn wipe(path: &Path, mode: u32) -> Result<(), Error> {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|e| Error::io(path, e))?;
+    for entry in fs::read_dir(path).map_err(|e| Error::io(path, e))? {
+        let entry = entry.map_err(|e| Error::io(path, e))?;
+        let path = entry.path();
+        let result = if entry.file_type().map(|x| x.is_dir()).unwrap_or(false) {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        result.map_err(|e| Error::io(&path, e))?;
+    }
+    Ok(())
+}
+
+/// Mode of the root of a freshly mounted workspace. Without a mode option,
+/// the kernel mounts a tmpfs root as 1777.
+fn root_mode(tmpfs: &Tmpfs) -> u32 {
+    tmpfs.config.options.mode.unwrap_or(0o1777)
+}
+
+/// A workspace is healthy when it is still a tmpfs mount point of its own
+/// and can be written to.
+fn is_healthy(tmpfs: &Tmpfs) -> bool {
+    let path = &tmpfs.src;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    let c_path = match CString::new(tmpfs.get_src().as_bytes()) {
+        Ok(x) => x,
+        Err(_) => return false,
+    };
+    if unsafe { statfs(c_path.as_ptr(), &mut stat) } == -1 || stat.f_type != libc::TMPFS_MAGIC {
+        return false;
+    }
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => return false,
+    };
+    match (fs::metadata(path), fs::metadata(parent)) {
+        (Ok(x), Ok(y)) if x.dev() != y.dev() => (),
+        _ => return false,
+    }
+    let probe = path.join(HEALTH_FILE);
+    fs::write(&probe, b"").is_ok() && fs::remove_file(&probe).is_ok()
+}
+
+impl TmpfsPool {
+    pub fn new(config: &TmpfsPoolConfig) -> Result<Self, Error> {
+        fs::create_dir_all(&config.root).map_err(|e| Error::io(&config.root, e))?;
+        let pool = Self {
+            inner: Arc::new(PoolInner {
+                config: config.clone(),
+                idle: Mutex::new(Vec::new()),
+                counter: AtomicUsize::new(0),
+            }),
+        };
+        for _ in 0..config.capacity {
+            let tmpfs = pool.mount()?;
+            pool.inner.idle.lock().unwrap().push(tmpfs);
+        }
+        Ok(pool)
+    }
+
+    fn mount(&self) -> Result<Tmpfs, Error> {
+        let index = self.inner.counter.fetch_add(1, Ordering::SeqCst);
+        let config = TmpfsConfig {
+            path: format!("{}/{}", self.inner.config.root.trim_end_matches('/'), index),
+            ..self.inner.config.template.clone()
+        };
+        let mut tmpfs = Tmpfs::from(&config);
+        tmpfs.create()?;
+        Ok(tmpfs)
+    }
+
+    /// Hands out an idle workspace, replacing the ones that fail the health
+    /// check, or mounts a new one when the pool is exhausted.
+    pub fn acquire(&self) -> Result<PooledTmpfs, Error> {
+        loop {
+            let tmpfs = self.inner.idle.lock().unwrap().pop();
+            match tmpfs {
+                Some(tmpfs) if is_healthy(&tmpfs) => {
+                    return Ok(PooledTmpfs { tmpfs: Some(tmpfs), pool: self.clone() });
+                },
+                Some(mut tmpfs) => {
+                    let _ = tmpfs.clean();
+                },
+                None => {
+                    return Ok(PooledTmpfs { tmpfs: Some(self.mount()?), pool: self.clone() });
+                },
+            }
+        }
+    }
+
+    /// Wipes a workspace and keeps it for the next `acquire`. Workspaces
+    /// that cannot be wiped, are unhealthy or exceed the capacity are
+    /// unmounted instead. The idle list is only locked after the wipe.
+    pub fn release(&self, mut tmpfs: Tmpfs) {
+        if wipe(&tmpfs.src, root_mode(&tmpfs)).is_err() || !is_healthy(&tmpfs) {
+            let _ = tmpfs.clean();
+            return;
+        }
+        tmpfs.target = None;
+        tmpfs.mounted = false;
+        tmpfs.reset_usage();
+        let mut idle = self.inner.idle.lock().unwrap();
+        if idle.len() >= self.inner.config.capacity {
+            drop(idle);
+            let _ = tmpfs.clean();
+            return;
+        }
+        idle.push(tmpfs);
+    }
+
+    /// Unmounts every idle workspace and removes the pool root once it is
+    /// empty.
+    pub fn close(&self) -> Result<(), Error> {
+        for mut tmpfs in self.inner.idle.lock().unwrap().drain(..) {
+            tmpfs.clean()?;
+        }
+        let _ = fs::remove_dir(&self.inner.config.root);
+        Ok(())
+    }
+}
+
+impl PooledTmpfs {
+    fn tmpfs(&self) -> &Tmpfs {
+        self.tmpfs.as_ref().unwrap()
+    }
+
+    fn tmpfs_mut(&mut self) -> &mut Tmpfs {
+        self.tmpfs.as_mut().unwrap()
+    }
+}
+
+impl BaseDir for PooledTmpfs {
+    fn create(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn clean(&mut self) -> Result<(), Error> {
+        wipe(&self.tmpfs().src, root_mode(self.tmpfs()))
+    }
+
+    fn get_src(&self) -> String {
+        self.tmpfs().get_src()
+    }
+
+    fn read_only(&self) -> bool {
+        self.tmpfs().read_only()
+    }
+
+    fn get_bind_string(&mut self, target: &String) -> String {
+        self.tmpfs_mut().get_bind_string(target)
+    }
+
+    fn usage(&mut self) -> Option<DiskUsage> {
+        BaseDir::usage(self.tmpfs_mut())
+    }
+
+    fn reset_usage(&mut self) {
+        self.tmpfs_mut().reset_usage()
+    }
+}
+
+impl Drop for PooledTmpfs {
+    fn drop(&mut self) {
+        if let Some(tmpfs) = self.tmpfs.take() {
+            self.pool.release(tmpfs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch;
+
+    #[test]
+    fn wipe_empties_but_keeps_the_directory() {
+        let dir = scratch("wipe");
+        let path = &dir.src;
+        fs::create_dir_all(path.join("a/b")).unwrap();
+        fs::write(path.join("a/b/c"), b"c").unwrap();
+        fs::write(path.join("d"), b"d").unwrap();
+        wipe(path, 0o755).unwrap();
+        assert!(path.is_dir());
+        assert_eq!(fs::read_dir(path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn wipe_restores_the_root_mode() {
+        let dir = scratch("mode");
+        let path = &dir.src;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o500)).unwrap();
+        wipe(path, 0o1777).unwrap();
+        assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o7777, 0o1777);
+        wipe(path, 0o750).unwrap();
+        assert_eq!(fs::metadata(path).unwrap().permissions().mode() & 0o7777, 0o750);
+    }
+
+    #[test]
+    fn plain_directories_are_not_healthy() {
+        let dir = scratch("plain");
+        let path = &dir.src;
+        let tmpfs = Tmpfs::from(&TmpfsConfig { path: path.to_string_lossy().to_string(), ..TmpfsConfig::default() });
+        assert!(!is_healthy(&tmpfs));
+        fs::remove_dir_all(path).unwrap();
+        assert!(!is_healthy(&tmpfs));
+    }
+
+    #[test]
+    fn unhealthy_workspaces_are_not_kept() {
+        let dir = scratch("release");
+        let root = &dir.src;
+        let pool = TmpfsPool::new(&TmpfsPoolConfig {
+            capacity: 0,
+            ..TmpfsPoolConfig::new(root.to_string_lossy().to_string())
+        }).unwrap();
+        let path = root.join("0");
+        fs::create_dir_all(&path).unwrap();
+        pool.release(Tmpfs::from(&TmpfsConfig { path: path.to_string_lossy().to_string(), ..TmpfsConfig::default() }));
+        assert!(pool.inner.idle.lock().unwrap().is_empty());
+        pool.close().unwrap();
+    }
+}
@@ -1,22 +1,86 @@
 use std::ffi::CString;
 use std::fmt::format;
 use std::fs;
-use std::fs::Permissions;
-use std::os::unix::prelude::PermissionsExt;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use errno::errno;
-use libc::{mount, umount};
+use libc::{mount, statvfs, umount};
 use crate::dir_utils::traits::BaseDir;
+use crate::error::Error;
+
+/// Mount source of the tmpfs workspaces, followed by the pid of the judger
+/// that mounted them so stale mounts can be told apart from live ones.
+pub const MOUNT_SOURCE_PREFIX: &str = "jcoder-";
+
+/// Left in a workspace that is kept after the judger exits, so that
+/// `sweep_stale_mounts` does not remove it on the next start.
+pub const KEEP_MARKER: &str = ".jcoder_keep";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HugePages {
+    Never,
+    Always,
+    WithinSize,
+    Advise,
+}
+
+/// Options of the tmpfs mount itself. `read_only` makes the filesystem
+/// read-only for everybody, unlike `TmpfsConfig::read_only` which only
+/// affects the bind mount into the sandbox.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MountOptions {
+    pub read_only: bool,
+    pub noexec: bool,
+    pub nosuid: bool,
+    pub nodev: bool,
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub huge: Option<HugePages>,
+}
+
+impl MountOptions {
+    pub fn flags(&self) -> libc::c_ulong {
+        let mut flags = 0;
+        if self.read_only { flags |= libc::MS_RDONLY; }
+        if self.noexec { flags |= libc::MS_NOEXEC; }
+        if self.nosuid { flags |= libc::MS_NOSUID; }
+        if self.nodev { flags |= libc::MS_NODEV; }
+        flags
+    }
+
+    /// Builds the tmpfs data string, so that the root mode and ownership are
+    /// set on the mounted filesystem rather than on the hidden mount point.
+    pub fn data(&self, size: usize, inodes: usize) -> String {
+        let mut data = vec![format!("size={}M", size), format!("nr_inodes={}", inodes)];
+        if let Some(mode) = self.mode {
+            data.push(format!("mode={:o}", mode));
+        }
+        if let Some(uid) = self.uid {
+            data.push(format!("uid={}", uid));
+        }
+        if let Some(gid) = self.gid {
+            data.push(format!("gid={}", gid));
+        }
+        if let Some(huge) = self.huge {
+            data.push(format!("huge={}", match huge {
+                HugePages::Never => "never",
+                HugePages::Always => "always",
+                HugePages::WithinSize => "within_size",
+                HugePages::Advise => "advise",
+            }));
+        }
+        data.join(",")
+    }
+}
 
 #[derive(Clone)]
 pub struct TmpfsConfig {
     pub size: usize,
     pub inodes: usize,
     pub path: String,
-    pub flag: u64,
-    pub permissions: Permissions,
+    pub options: MountOptions,
     pub read_only: bool
 }
 
@@ -26,50 +90,120 @@ pub struct Tmpfs {
     pub src: PathBuf,
     pub target: Option<PathBuf>,
     pub created: bool,
-    pub mounted: bool
+    pub mounted: bool,
+    pub peak_bytes: u64,
+    pub peak_inodes: u64,
+}
+
+/// Space and inodes used on a filesystem, together with the highest values
+/// seen so far and the limits it was mounted with.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DiskUsage {
+    pub bytes: u64,
+    pub inodes: u64,
+    pub peak_bytes: u64,
+    pub peak_inodes: u64,
+    pub max_bytes: u64,
+    pub max_inodes: u64,
+    pub block_size: u64,
+}
+
+impl DiskUsage {
+    /// Whether the filesystem ran out of blocks or inodes at some point,
+    /// which is what makes writes fail with ENOSPC.
+    pub fn is_full(&self) -> bool {
+        (self.max_bytes > 0 && self.peak_bytes + self.block_size > self.max_bytes) ||
+            (self.max_inodes > 0 && self.peak_inodes >= self.max_inodes)
+    }
+
+    /// Keeps the highest values of `self` and `other`.
+    pub fn merge(&mut self, other: &DiskUsage) {
+        self.peak_bytes = self.peak_bytes.max(other.peak_bytes).max(other.bytes);
+        self.peak_inodes = self.peak_inodes.max(other.peak_inodes).max(other.inodes);
+        self.bytes = other.bytes;
+        self.inodes = other.inodes;
+        self.max_bytes = other.max_bytes;
+        self.max_inodes = other.max_inodes;
+        self.block_size = other.block_size;
+    }
+}
+
+/// Reads the current usage of the filesystem holding `path`. The peaks are
+/// the current values, so that samples can be merged.
+pub fn disk_usage(path: &Path) -> Result<DiskUsage, Error> {
+    let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    unsafe {
+        if statvfs(c_path.as_ptr(), &mut stat) == -1 {
+            return Err(Error::io(path, std::io::Error::last_os_error()));
+        }
+    }
+    let block_size = stat.f_frsize;
+    let bytes = (stat.f_blocks - stat.f_bfree) * block_size;
+    let inodes = stat.f_files - stat.f_ffree;
+    Ok(DiskUsage {
+        bytes,
+        inodes,
+        peak_bytes: bytes,
+        peak_inodes: inodes,
+        max_bytes: stat.f_blocks * block_size,
+        max_inodes: stat.f_files,
+        block_size,
+    })
+}
+
+impl Tmpfs {
+    /// Reads the current usage of the mounted filesystem and updates the
+    /// peak values.
+    pub fn usage(&mut self) -> Result<DiskUsage, Error> {
+        let mut usage = disk_usage(&self.src)?;
+        self.peak_bytes = self.peak_bytes.max(usage.bytes);
+        self.peak_inodes = self.peak_inodes.max(usage.inodes);
+        usage.peak_bytes = self.peak_bytes;
+        usage.peak_inodes = self.peak_inodes;
+        Ok(usage)
+    }
 }
 
 impl BaseDir for Tmpfs {
-    fn create(&mut self) -> Result<(), String> {
+    fn create(&mut self) -> Result<(), Error> {
         if self.created == true {
             return Ok(());
         }
         if !self.src.is_dir() {
-            if fs::create_dir(&self.src).is_err() { return Err(errno().to_string()); }
-            if fs::set_permissions(&self.src, self.config.permissions.clone()).is_err() {
-                return Err(errno().to_string());
-            }
+            if let Err(e) = fs::create_dir(&self.src) { return Err(Error::io(&self.src, e)); }
         }
-        let data = format!("size={}M,nr_inodes={}", self.config.size, self.config.inodes);
+        let data = self.config.options.data(self.config.size, self.config.inodes);
+        let source = format!("{}{}", MOUNT_SOURCE_PREFIX, std::process::id());
         unsafe {
             let result = mount(
-                CString::new("tmpfs").unwrap().as_ptr(),
+                CString::new(source.as_bytes()).unwrap().as_ptr(),
                 CString::new(self.config.path.as_bytes()).unwrap().as_ptr(),
                 CString::new("tmpfs").unwrap().as_ptr(),
-                self.config.flag,
+                self.config.options.flags(),
                 CString::new(data.as_bytes()).unwrap().as_ptr().cast(),
             );
             if result == -1 {
-                return Err(errno().to_string());
+                return Err(Error::mount(&self.src));
             }
         }
         self.created = true;
         Ok(())
     }
 
-    fn clean(&mut self) -> Result<(), String> {
+    fn clean(&mut self) -> Result<(), Error> {
         if self.created == false {
             return Ok(());
         }
         unsafe {
             let result = umount(CString::new(self.config.path.as_bytes()).unwrap().as_ptr());
             if result == -1 {
-                return Err(errno().to_string());
+                return Err(Error::mount(&self.src));
             }
         }
         match fs::remove_dir_all(&self.src) {
             Ok(_) => { self.created = false; Ok(()) },
-            Err(e) => Err(e.to_string())
+            Err(e) => Err(Error::io(&self.src, e))
         }
     }
 
@@ -89,4 +223,114 @@ impl BaseDir for Tmpfs {
         }
         format!("--bindmount={}:{}", self.get_src(), target)
     }
-}
\ No newline at end of file
+
+    fn usage(&mut self) -> Option<DiskUsage> {
+        if !self.created {
+            return None;
+        }
+        Tmpfs::usage(self).ok()
+    }
+
+    fn reset_usage(&mut self) {
+        self.peak_bytes = 0;
+        self.peak_inodes = 0;
+    }
+
+    fn mark_kept(&mut self) {
+        if self.created {
+            let _ = fs::write(self.src.join(KEEP_MARKER), b"");
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(peak_bytes: u64, peak_inodes: u64) -> DiskUsage {
+        DiskUsage {
+            peak_bytes,
+            peak_inodes,
+            max_bytes: 1 << 20,
+            max_inodes: 100,
+            block_size: 4096,
+            ..DiskUsage::default()
+        }
+    }
+
+    #[test]
+    fn full_when_no_block_or_inode_is_left() {
+        assert!(!usage(0, 0).is_full());
+        assert!(!usage((1 << 20) - 4096, 99).is_full());
+        assert!(usage((1 << 20) - 4095, 0).is_full());
+        assert!(usage(0, 100).is_full());
+    }
+
+    #[test]
+    fn unlimited_filesystem_is_never_full() {
+        let usage = DiskUsage { peak_bytes: u64::MAX / 2, peak_inodes: u64::MAX, block_size: 4096, ..DiskUsage::default() };
+        assert!(!usage.is_full());
+    }
+
+    #[test]
+    fn merge_keeps_peaks_and_latest_values() {
+        let mut merged = DiskUsage::default();
+        merged.merge(&DiskUsage { bytes: 300, inodes: 3, peak_bytes: 500, peak_inodes: 4, ..usage(0, 0) });
+        merged.merge(&DiskUsage { bytes: 100, inodes: 1, peak_bytes: 0, peak_inodes: 0, ..usage(0, 0) });
+        assert_eq!((merged.bytes, merged.inodes), (100, 1));
+        assert_eq!((merged.peak_bytes, merged.peak_inodes), (500, 4));
+        assert_eq!(merged.max_bytes, 1 << 20);
+    }
+
+    #[test]
+    fn disk_usage_reads_the_filesystem_of_a_path() {
+        let usage = disk_usage(&std::env::temp_dir()).unwrap();
+        assert!(usage.block_size > 0 && usage.bytes <= usage.max_bytes);
+        assert_eq!((usage.peak_bytes, usage.peak_inodes), (usage.bytes, usage.inodes));
+        assert!(matches!(disk_usage(Path::new("/nonexistent/jcoder")), Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn reset_usage_forgets_the_peaks() {
+        let mut tmpfs = Tmpfs { peak_bytes: 4096, peak_inodes: 2, ..Tmpfs::default() };
+        tmpfs.reset_usage();
+        assert_eq!((tmpfs.peak_bytes, tmpfs.peak_inodes), (0, 0));
+    }
+
+    #[test]
+    fn default_data_only_sets_size_inodes_and_mode() {
+        assert_eq!(MountOptions::default().data(64, 1024), "size=64M,nr_inodes=1024,mode=777");
+        assert_eq!(MountOptions::default().flags(), 0);
+    }
+
+    #[test]
+    fn data_includes_ownership_and_huge_pages() {
+        let options = MountOptions {
+            mode: Some(0o1770),
+            uid: Some(1000),
+            gid: Some(0),
+            huge: Some(HugePages::WithinSize),
+            ..MountOptions::default()
+        };
+        assert_eq!(options.data(8, 16), "size=8M,nr_inodes=16,mode=1770,uid=1000,gid=0,huge=within_size");
+        let options = MountOptions { mode: None, ..MountOptions::default() };
+        assert_eq!(options.data(1, 2), "size=1M,nr_inodes=2");
+    }
+
+    #[test]
+    fn flags_follow_the_options() {
+        let options = MountOptions { read_only: true, noexec: true, nosuid: true, nodev: true, ..MountOptions::default() };
+        assert_eq!(options.flags(), libc::MS_RDONLY | libc::MS_NOEXEC | libc::MS_NOSUID | libc::MS_NODEV);
+        let options = MountOptions { nosuid: true, ..MountOptions::default() };
+        assert_eq!(options.flags(), libc::MS_NOSUID);
+    }
+
+    #[test]
+    fn huge_pages_parse_like_the_mount_option() {
+        assert_eq!("never".parse::<HugePages>().unwrap(), HugePages::Never);
+        assert_eq!("always".parse::<HugePages>().unwrap(), HugePages::Always);
+        assert_eq!("within_size".parse::<HugePages>().unwrap(), HugePages::WithinSize);
+        assert_eq!("advise".parse::<HugePages>().unwrap(), HugePages::Advise);
+        assert!("Always".parse::<HugePages>().is_err());
+        assert!("".parse::<HugePages>().is_err());
+    }
+}
@@ -1,15 +1,25 @@
-use std::fs::Permissions;
-use std::os::unix::prelude::PermissionsExt;
 use std::path::PathBuf;
+use std::str::FromStr;
+use crate::dir_utils::archive::ArchiveConfig;
 use crate::dir_utils::local::{Local, LocalConfig};
-use crate::dir_utils::tmpfs::{Tmpfs, TmpfsConfig};
+use crate::dir_utils::pool::TmpfsPoolConfig;
+use crate::dir_utils::tmpfs::{DiskUsage, HugePages, MountOptions, Tmpfs, TmpfsConfig};
+use crate::error::Error;
 
 pub trait BaseDir {
-    fn create(&mut self) -> Result<(), String>;
-    fn clean(&mut self) -> Result<(), String>;
+    fn create(&mut self) -> Result<(), Error>;
+    fn clean(&mut self) -> Result<(), Error>;
     fn get_src(&self) -> String;
     fn read_only(&self) -> bool;
     fn get_bind_string(&mut self, target: &String) -> String;
+    fn usage(&mut self) -> Option<DiskUsage> {
+        None
+    }
+    /// Forgets the peak usage, so that the next `usage` only covers what
+    /// happened since.
+    fn reset_usage(&mut self) {}
+    /// Called instead of `clean` when the directory is kept.
+    fn mark_kept(&mut self) {}
 }
 
 impl Default for TmpfsConfig {
@@ -18,18 +28,38 @@ impl Default for TmpfsConfig {
             size: 64,
             inodes: 1024,
             path: String::default(),
-            flag: 0,
-            permissions: Permissions::from_mode(777),
+            options: MountOptions::default(),
             read_only: false,
         }
     }
 }
 
-impl TmpfsConfig {
-    pub fn new(path: String) -> Self {
+impl Default for MountOptions {
+    fn default() -> Self {
         Self {
-            path,
-            ..Self::default()
+            read_only: false,
+            noexec: false,
+            nosuid: false,
+            nodev: false,
+            mode: Some(0o777),
+            uid: None,
+            gid: None,
+            huge: None,
+        }
+    }
+}
+
+/// Parses the values accepted by the `huge=` tmpfs mount option.
+impl FromStr for HugePages {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(HugePages::Never),
+            "always" => Ok(HugePages::Always),
+            "within_size" => Ok(HugePages::WithinSize),
+            "advise" => Ok(HugePages::Advise),
+            _ => Err(Error::config("huge", format!("unknown huge page policy {:?}", s))),
         }
     }
 }
@@ -42,6 +72,8 @@ impl From<&TmpfsConfig> for Tmpfs {
             target: None,
             created: false,
             mounted: false,
+            peak_bytes: 0,
+            peak_inodes: 0,
         }
     }
 }
@@ -78,4 +110,33 @@ impl LocalConfig {
             ..Self::default()
         }
     }
+}
+
+impl Default for TmpfsPoolConfig {
+    fn default() -> Self {
+        Self {
+            root: "/tmp/jcoder_pool".to_string(),
+            capacity: 4,
+            template: TmpfsConfig::default(),
+        }
+    }
+}
+
+impl TmpfsPoolConfig {
+    pub fn new(root: String) -> Self {
+        Self {
+            root,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 1 << 30,
+            max_entries: 10000,
+            allow_symlinks: false,
+        }
+    }
 }
\ No newline at end of file
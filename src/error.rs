@@ -0,0 +1,133 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::nsjail_judger::result::JudgerResult;
+
+/// Errors raised by the judging machinery itself. Every variant is a system
+/// error (SE); failures of the contestant's program are reported through
+/// `JudgerResult` and `BaseResult` instead.
+#[derive(Debug)]
+pub enum Error {
+    Mount { path: PathBuf, source: io::Error },
+    Permission { path: PathBuf, source: io::Error },
+    NotFound { path: PathBuf },
+    Io { path: PathBuf, source: io::Error },
+    Spawn { exec: String, source: io::Error },
+    ReportParse { path: PathBuf, reason: String },
+    CompareIo { path: PathBuf, source: io::Error },
+    Config { path: PathBuf, reason: String },
+    Integrity { path: PathBuf, reason: String },
+    Archive { path: PathBuf, reason: String },
+    Task { name: String, reason: String },
+}
+
+impl Error {
+    /// Classifies a filesystem error by its kind so that missing files and
+    /// permission problems get their own variants.
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        match source.kind() {
+            io::ErrorKind::NotFound => Error::NotFound { path },
+            io::ErrorKind::PermissionDenied => Error::Permission { path, source },
+            _ => Error::Io { path, source },
+        }
+    }
+
+    /// Builds an error from the current `errno` after a failed mount(2) or
+    /// umount(2) call.
+    pub fn mount(path: impl AsRef<Path>) -> Self {
+        let source = io::Error::last_os_error();
+        let path = path.as_ref().to_path_buf();
+        match source.kind() {
+            io::ErrorKind::PermissionDenied => Error::Permission { path, source },
+            _ => Error::Mount { path, source },
+        }
+    }
+
+    pub fn compare(path: impl AsRef<Path>, source: io::Error) -> Self {
+        Error::CompareIo { path: path.as_ref().to_path_buf(), source }
+    }
+
+    pub fn config(path: impl AsRef<Path>, reason: impl ToString) -> Self {
+        Error::Config { path: path.as_ref().to_path_buf(), reason: reason.to_string() }
+    }
+
+    /// A helper program of the problem, such as a generator or the reference
+    /// solution, did not exit cleanly.
+    pub fn task(name: &str, result: &JudgerResult) -> Self {
+        Error::Task {
+            name: name.to_string(),
+            reason: format!("exit code {}, signal {}", result.exit_code, result.exit_signal),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Mount { path, source } =>
+                write!(f, "Error occur when mount or unmount: {} :{}", path.display(), source),
+            Error::Permission { path, source } =>
+                write!(f, "Permission denied: {} :{}", path.display(), source),
+            Error::NotFound { path } =>
+                write!(f, "No such file or directory: {}", path.display()),
+            Error::Io { path, source } =>
+                write!(f, "Error occur when access: {} :{}", path.display(), source),
+            Error::Spawn { exec, source } =>
+                write!(f, "Error occur when spawn sandbox: {} :{}", exec, source),
+            Error::ReportParse { path, reason } =>
+                write!(f, "Error occur when parse report: {} :{}", path.display(), reason),
+            Error::CompareIo { path, source } =>
+                write!(f, "Error occur when read file to compare: {} :{}", path.display(), source),
+            Error::Config { path, reason } =>
+                write!(f, "Error occur when parse config: {} :{}", path.display(), reason),
+            Error::Integrity { path, reason } =>
+                write!(f, "Integrity check failed: {} :{}", path.display(), reason),
+            Error::Archive { path, reason } =>
+                write!(f, "Error occur when extract archive: {} :{}", path.display(), reason),
+            Error::Task { name, reason } =>
+                write!(f, "Error occur when run task: {} :{}", name, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Mount { source, .. } |
+            Error::Permission { source, .. } |
+            Error::Io { source, .. } |
+            Error::Spawn { source, .. } |
+            Error::CompareIo { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_errors_are_classified_by_kind() {
+        let error = |kind| Error::io("/data", io::Error::from(kind));
+        assert!(matches!(error(io::ErrorKind::NotFound), Error::NotFound { .. }));
+        assert!(matches!(error(io::ErrorKind::PermissionDenied), Error::Permission { .. }));
+        assert!(matches!(error(io::ErrorKind::InvalidData), Error::Io { .. }));
+    }
+
+    #[test]
+    fn display_names_the_path() {
+        let error = Error::config("/data/task.toml", "missing field");
+        assert_eq!(error.to_string(), "Error occur when parse config: /data/task.toml :missing field");
+        let result = JudgerResult { exit_code: 1, exit_signal: 0, ..JudgerResult::default() };
+        assert_eq!(Error::task("generator", &result).to_string(), "Error occur when run task: generator :exit code 1, signal 0");
+    }
+
+    #[test]
+    fn only_io_variants_have_a_source() {
+        use std::error::Error as _;
+        assert!(Error::io("/data", io::Error::from(io::ErrorKind::InvalidData)).source().is_some());
+        assert!(Error::config("/data", "reason").source().is_none());
+    }
+}
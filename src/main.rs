@@ -6,20 +6,31 @@ use core::cell::RefCell;
 use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use difference::{Changeset, Difference};
 use fs_extra::{copy_items, dir};
 use fs_extra::dir::{copy, CopyOptions};
-use crate::compare::compare::{compare_string, CompareConfig, remove_end_of_text_enters, compare_file};
-use crate::compare::results::{BaseResult, BaseStatus};
+use crate::compare::compare::{CompareConfig, judge_file, judge_any, answer_files};
+use crate::compare::render::{html, side_by_side, unified, RenderConfig};
+use crate::compare::results::{BaseResult, BaseStatus, CompareResult};
+use crate::dir_utils::guard::{sweep_stale_mounts, DirGuard};
 use crate::dir_utils::local::{Local, LocalConfig};
-use crate::dir_utils::tmpfs::{Tmpfs, TmpfsConfig};
+use crate::dir_utils::manifest::Manifest;
+use crate::dir_utils::pool::{TmpfsPool, TmpfsPoolConfig};
+use crate::dir_utils::tmpfs::{HugePages, Tmpfs, TmpfsConfig};
 use crate::dir_utils::traits::BaseDir;
+use crate::nsjail_judger::cache::{CacheConfig, CompileCache, DEFAULT_CACHE_PATH};
 use crate::nsjail_judger::judger::{NsjailConfig, NsjailJudger, NsjailTask};
 use crate::nsjail_judger::result::JudgerResult;
+use crate::nsjail_judger::stress::{stress, StressConfig, StressReport};
+use crate::nsjail_judger::hack::{hack, HackConfig};
+use crate::nsjail_judger::validate::{test_inputs, validate};
+use crate::nsjail_judger::answer::{generate_answers, AnswerConfig};
+use crate::error::Error;
 
 lazy_static! {
-    static ref USEDDIRS: Mutex<Vec<Arc<Mutex<dyn BaseDir + Send + Sync>>>> = Mutex::new(Vec::new());
+    static ref USEDDIRS: Mutex<Vec<Weak<Mutex<dyn BaseDir + Send + Sync>>>> = Mutex::new(Vec::new());
+    static ref KEEPDIRS: bool = std::env::var("JCODER_KEEP_DIRS").is_ok();
     static ref LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
     static ref ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
     static ref STATUS: Mutex<BaseStatus> = Mutex::new(BaseStatus::PENDING);
@@ -28,12 +39,15 @@ lazy_static! {
 
 mod dir_utils;
 mod compare;
+mod error;
 mod nsjail_judger;
+#[cfg(test)]
+mod testing;
 
 
 macro_rules! Eval {
     (clean) => {
-        for dir in USEDDIRS.lock().unwrap().iter_mut() {
+        for dir in USEDDIRS.lock().unwrap().iter().filter_map(|x| x.upgrade()) {
             let _tmp = dir.deref().lock().unwrap().get_src();
             match dir.lock().unwrap().clean() {
                 Ok(_) => LOGS.lock().unwrap().push(
@@ -43,18 +57,17 @@ macro_rules! Eval {
             };
         }
     };
-    (use tmpfs $path: expr, as $name: ident) => {
-        Eval!(create tmpfs with TmpfsConfig::new($path.to_string()), as $name);
-    };
     (use localfs $path: expr, as $name: ident) => {
         Eval!(create localfs with LocalConfig::new($path.to_string()), as $name);
     };
     (create tmpfs with $args: expr, as $name: ident) => {
-        let mut $name = Arc::new(Mutex::new(Tmpfs::from(&$args)));
+        let $name = Arc::new(Mutex::new(DirGuard::new(Tmpfs::from(&$args))));
+        $name.lock().unwrap().keep(*KEEPDIRS);
         let _tmp = $name.lock().unwrap().get_src();
         match $name.lock().unwrap().create() {
             Ok(_) => {
-                USEDDIRS.lock().unwrap().push($name.clone());
+                let _weak = Arc::downgrade(&$name);
+                USEDDIRS.lock().unwrap().push(_weak);
                 LOGS.lock().unwrap().push(
                     format!("Successfully Created Directory: {}", &_tmp)
                 );
@@ -63,11 +76,13 @@ macro_rules! Eval {
         };
     };
     (create localfs with $args: expr, as $name: ident) => {
-        let mut $name = Arc::new(Mutex::new(Local::from(&$args)));
+        let $name = Arc::new(Mutex::new(DirGuard::new(Local::from(&$args))));
+        $name.lock().unwrap().keep(*KEEPDIRS);
         let _tmp = $name.lock().unwrap().get_src();
         match $name.lock().unwrap().create() {
             Ok(_) => {
-                USEDDIRS.lock().unwrap().push($name.clone());
+                let _weak = Arc::downgrade(&$name);
+                USEDDIRS.lock().unwrap().push(_weak);
                 LOGS.lock().unwrap().push(
                     format!("Successfully Created Directory: {}", &_tmp)
                 );
@@ -75,6 +90,97 @@ macro_rules! Eval {
             Err(e) => ERRORS.lock().unwrap().push(e.to_string()),
         };
     };
+    (create pool with $args: expr, as $name: ident) => {
+        let $name = match TmpfsPool::new(&$args) {
+            Ok(pool) => {
+                LOGS.lock().unwrap().push(
+                    format!("Successfully Created Pool: {}", &$args.root)
+                );
+                Some(pool)
+            },
+            Err(e) => {
+                ERRORS.lock().unwrap().push(e.to_string());
+                *STATUS.lock().unwrap() = BaseStatus::SE;
+                None
+            }
+        };
+    };
+    (close $pool: ident) => {
+        match $pool.close() {
+            Ok(_) => LOGS.lock().unwrap().push(
+                "Successfully Closed Pool".to_string()
+            ),
+            Err(e) => ERRORS.lock().unwrap().push(e.to_string()),
+        };
+    };
+    (acquire $name: ident from $pool: ident) => {
+        let mut $name = None;
+        match $pool.acquire() {
+            Ok(dir) => {
+                let _tmp = dir.get_src();
+                $name = Some(Arc::new(Mutex::new(dir)));
+                LOGS.lock().unwrap().push(
+                    format!("Successfully Acquired Directory: {}", &_tmp)
+                );
+            },
+            Err(e) => ERRORS.lock().unwrap().push(e.to_string()),
+        };
+    };
+    (extract $archive: expr, to $to: ident, with $config: expr) => {
+        let _archive = std::path::PathBuf::from(&$archive);
+        let _result = crate::dir_utils::archive::extract(&_archive, &*$to.lock().unwrap(), &$config);
+        match _result {
+            Ok(num) => LOGS.lock().unwrap().push(
+                format!("Successfully Extracted {} Items From {} to {}",
+                        num, _archive.display(), $to.lock().unwrap().get_src())
+            ),
+            Err(e) => {
+                ERRORS.lock().unwrap().push(e.to_string());
+                *STATUS.lock().unwrap() = BaseStatus::SE;
+            }
+        };
+    };
+    (extract $archive: expr, to $to: ident) => {
+        Eval!(extract $archive, to $to, with crate::dir_utils::archive::ArchiveConfig::default());
+    };
+    (verify $dir: ident) => {
+        {
+            let _dir = $dir.lock().unwrap();
+            match Manifest::load(&*_dir).and_then(|x| x.map_or(Ok(false), |x| x.check(&*_dir).map(|_| true))) {
+                Ok(true) => LOGS.lock().unwrap().push(
+                    format!("Successfully Verified Directory: {}", _dir.get_src())
+                ),
+                Ok(false) => LOGS.lock().unwrap().push(
+                    format!("Warning: Skipped Verifying Directory Without Manifest: {}", _dir.get_src())
+                ),
+                Err(e) => {
+                    ERRORS.lock().unwrap().push(e.to_string());
+                    *STATUS.lock().unwrap() = BaseStatus::SE;
+                }
+            };
+        }
+    };
+    (verify $to: ident against $from: ident) => {
+        {
+            let _manifest = {
+                let _from = $from.lock().unwrap();
+                Manifest::load(&*_from)
+            };
+            let _to = $to.lock().unwrap();
+            match _manifest.and_then(|x| x.map_or(Ok(false), |x| x.check(&*_to).map(|_| true))) {
+                Ok(true) => LOGS.lock().unwrap().push(
+                    format!("Successfully Verified Directory: {}", _to.get_src())
+                ),
+                Ok(false) => LOGS.lock().unwrap().push(
+                    format!("Warning: Skipped Verifying Directory Without Manifest: {}", _to.get_src())
+                ),
+                Err(e) => {
+                    ERRORS.lock().unwrap().push(e.to_string());
+                    *STATUS.lock().unwrap() = BaseStatus::SE;
+                }
+            };
+        }
+    };
     (copydir $from: ident to $to: ident, with $config: ident) => {
         let f = $from.lock().unwrap().get_src();
         let t = $to.lock().unwrap().get_src();
@@ -121,38 +227,55 @@ macro_rules! Eval {
             )
         );
     };
-    (compare file $ans: expr, to $output: expr, with $args: expr, as $name: ident) => {
-        let mut $name = compare_file(&$ans, &$output, &$args);
+    (judge file $ans: expr, to $output: expr, with $args: expr, as $name: ident) => {
+        let mut $name = judge_file(&$ans, &$output, &$args);
         if let Err(ref e) = $name {
-            ERRORS.lock().unwrap().push(e.clone());
-            *STATUS.lock().unwrap() = BaseStatus::UKE;
+            ERRORS.lock().unwrap().push(e.to_string());
+            *STATUS.lock().unwrap() = BaseStatus::SE;
         } else {
             LOGS.lock().unwrap().push(
                 format!(
-                    "Successfully Compared File {} to {}",
+                    "Successfully Judged File {} to {}",
                     &$ans, &$output
                 )
             );
         }
     };
-    (compare file $ans: expr, to $output: expr, as $name: ident) => {
-        let mut $name = compare_file(&$ans, &$output, &CompareConfig::default());
+    (judge files $answers: expr, to $output: expr, with $args: expr, as $name: ident) => {
+        let $name = judge_any(&$answers, &$output, &$args);
         if let Err(ref e) = $name {
-            ERRORS.lock().unwrap().push(e.clone());
-            *STATUS.lock().unwrap() = BaseStatus::UKE;
+            ERRORS.lock().unwrap().push(e.to_string());
+            *STATUS.lock().unwrap() = BaseStatus::SE;
         } else {
             LOGS.lock().unwrap().push(
                 format!(
-                    "Successfully Compared File {} to {}",
-                    &$ans, &$output
+                    "Successfully Judged File {} to {} answers",
+                    &$output, $answers.len()
                 )
             );
         }
     };
+    (update result $result: ident by compare {$compared: expr}) => {
+        $result.update_by_compare(&$compared);
+        *STATUS.lock().unwrap() = $result.status;
+    };
     (update result $result: ident by changeset {$changeset: expr}) => {
         $result.update(&$changeset);
         *STATUS.lock().unwrap() = $result.status;
     };
+    (update result $result: ident by judger {$judger_result: expr}) => {
+        $result.update_by_judger(&$judger_result);
+        *STATUS.lock().unwrap() = $result.status;
+    };
+    (show diff of $result: ident) => {
+        if let Some(ref changeset) = $result.changeset {
+            if changeset.distance != 0 {
+                LOGS.lock().unwrap().push(
+                    crate::compare::render::unified(changeset, &Default::default())
+                );
+            }
+        }
+    };
     (push result $result: ident) => {
         COMEPARERESULT.lock().unwrap().push(Arc::new($result));
     };
@@ -165,16 +288,351 @@ macro_rules! Eval {
     (mount $fs: ident to $judger: ident at $target: literal) => {
         $judger.mount_all($fs, &$target.to_string());
     };
+    (write outputs of $judger: ident to $fs: ident) => {
+        $judger.use_output_dir($fs);
+    };
+    (set $key: literal to $value: expr, in $judger: ident) => {
+        $judger.set_var($key, &$value.to_string());
+    };
     (run all tasks in $judger: ident as $name: ident) => {
-        let mut $name = $judger.run_all();
+        let $name = match $judger.run_all() {
+            Ok(result) => result,
+            Err(e) => {
+                ERRORS.lock().unwrap().push(e.to_string());
+                *STATUS.lock().unwrap() = BaseStatus::SE;
+                vec![]
+            }
+        };
+    };
+    (show files of $result: ident) => {
+        for file in $result.iter().flatten().flat_map(|x| x.files.iter()) {
+            LOGS.lock().unwrap().push(
+                format!("Successfully Collected File: {} ({} bytes{})", file.path, file.size,
+                        if file.truncated { format!(", truncated to {}", file.data.len()) } else { String::new() })
+            );
+        }
     };
     (run tasks $index: literal in $judger: ident) => {
-        $judger.run($index);
+        if let Err(e) = $judger.run($index) {
+            ERRORS.lock().unwrap().push(e.to_string());
+            *STATUS.lock().unwrap() = BaseStatus::SE;
+        }
+    };
+}
+
+fn cache_stats(args: &[String]) {
+    let path = args.first().cloned().unwrap_or(DEFAULT_CACHE_PATH.to_string());
+    match CompileCache::new(&CacheConfig::new(path.clone())).stats() {
+        Ok(stats) => {
+            println!("cache: {}", path);
+            println!("entries: {}", stats.entries);
+            println!("size: {} bytes", stats.size);
+            println!("hits: {}", stats.hits);
+            println!("misses: {}", stats.misses);
+            println!("evictions: {}", stats.evictions);
+        },
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+fn store_manifest(args: &[String]) {
+    let path = match args.first() {
+        Some(x) => x,
+        None => return eprintln!("usage: manifest <dir>"),
+    };
+    let dir = Local::from(&LocalConfig::new(path.clone()));
+    match Manifest::compute(&dir).and_then(|x| x.store(&dir).map(|_| x)) {
+        Ok(manifest) => println!("stored manifest of {} files in {}", manifest.entries.len(), path),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+fn load_config(path: &str) -> Result<NsjailConfig, Error> {
+    let content = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    toml::from_str(&content).map_err(|e| Error::config(path, e))
+}
+
+/// Splits the arguments of a command into positional arguments and
+/// `--key` or `--key=value` options.
+fn parse_args(args: &[String]) -> (Vec<&String>, HashMap<&str, &str>) {
+    let mut positional = vec![];
+    let mut options = HashMap::new();
+    for arg in args {
+        match arg.strip_prefix("--") {
+            Some(option) => {
+                let (key, value) = option.split_once('=').unwrap_or((option, ""));
+                options.insert(key, value);
+            },
+            None => positional.push(arg),
+        }
+    }
+    (positional, options)
+}
+
+/// Builds the compare config of a command from its `--mode`, `--unicode`,
+/// `--ignore-case` and `--width` options.
+fn compare_config(options: &HashMap<&str, &str>) -> Result<CompareConfig, Error> {
+    let mut config = CompareConfig::default();
+    if let Some(mode) = options.get("mode") {
+        config.mode = mode.parse()?;
+    }
+    if let Some(form) = options.get("unicode") {
+        config.normalize.unicode = Some(form.parse()?);
+    }
+    if let Some(unit) = options.get("score") {
+        config.score = Some(unit.parse()?);
+    }
+    config.normalize.ignore_case = options.contains_key("ignore-case");
+    config.normalize.width = options.contains_key("width");
+    Ok(config)
+}
+
+fn print_compared(result: &CompareResult) {
+    print_rendered(result, unified);
+}
+
+fn print_rendered(result: &CompareResult, render: fn(&Changeset, &RenderConfig) -> String) {
+    println!("{:?}", result.status);
+    if let Some(score) = result.score {
+        println!("score: {}", score);
+    }
+    if let Some(info) = &result.info {
+        println!("{}", info);
+    }
+    if let Some(changeset) = result.changeset.as_ref().filter(|x| x.distance != 0) {
+        println!("{}", render(changeset, &RenderConfig::default()).trim_end());
+    }
+}
+
+/// `diff <answer> <output> [--side-by-side|--html] [--mode=<mode>] [--score=<unit>]`:
+/// judges an output against the answer and renders the difference.
+fn show_diff(args: &[String]) {
+    let (paths, options) = parse_args(args);
+    let (ans, output) = match paths[..] {
+        [ans, output] => (ans, output),
+        _ => return eprintln!("usage: diff <answer> <output> [--side-by-side|--html] [--mode=<mode>] [--score=<unit>]"),
+    };
+    let render: fn(&Changeset, &RenderConfig) -> String = if options.contains_key("html") {
+        html
+    } else if options.contains_key("side-by-side") {
+        side_by_side
+    } else {
+        unified
+    };
+    let config = match compare_config(&options) {
+        Ok(x) => x,
+        Err(e) => return eprintln!("{}", e),
+    };
+    match judge_file(ans, output, &config) {
+        Ok(result) => print_rendered(&result, render),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// `stress <config.toml> [iterations] [seed] [--mode=<mode>]`: the config
+/// holds the `generator`, `reference` and `submission` tasks.
+fn stress_test(args: &[String]) {
+    let (args, options) = parse_args(args);
+    let config = match args.first().map(|x| load_config(x)) {
+        Some(Ok(x)) => x,
+        Some(Err(e)) => return eprintln!("{}", e),
+        None => return eprintln!("usage: stress <config.toml> [iterations] [seed] [--mode=<mode>]"),
     };
+    let mut stress_config = StressConfig::default();
+    if let Some(x) = args.get(1).and_then(|x| x.parse().ok()) {
+        stress_config.iterations = x;
+    }
+    if let Some(x) = args.get(2).and_then(|x| x.parse().ok()) {
+        stress_config.seed = x;
+    }
+    match compare_config(&options) {
+        Ok(x) => stress_config.compare = x,
+        Err(e) => return eprintln!("{}", e),
+    }
+    Eval!(use nsjail with config, as jail);
+    match stress(&mut jail, &stress_config) {
+        Ok(StressReport { runs, failure: None }) => println!("passed {} run(s)", runs),
+        Ok(StressReport { runs, failure: Some(failure) }) => {
+            println!("failed on run {}, seed {}", runs, failure.seed);
+            println!("input saved to {}", failure.input_path);
+            match failure.result {
+                Some(result) => print_compared(&result),
+                None => println!(
+                    "submission exited with code {}, signal {}",
+                    failure.submission.exit_code, failure.submission.exit_signal
+                ),
+            }
+        },
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// `hack <config.toml> <input> [--mode=<mode>]`: the config holds the
+/// `validator`, `reference` and `target` tasks.
+fn hack_test(args: &[String]) {
+    let (args, options) = parse_args(args);
+    let (config, input) = match (args.first().map(|x| load_config(x)), args.get(1)) {
+        (Some(Ok(x)), Some(input)) => (x, input),
+        (Some(Err(e)), _) => return eprintln!("{}", e),
+        _ => return eprintln!("usage: hack <config.toml> <input> [--mode=<mode>]"),
+    };
+    let compare = match compare_config(&options) {
+        Ok(x) => x,
+        Err(e) => return eprintln!("{}", e),
+    };
+    let hack_config = HackConfig {
+        input_path: input.to_string(),
+        compare,
+        ..HackConfig::default()
+    };
+    Eval!(use nsjail with config, as jail);
+    match hack(&mut jail, &hack_config) {
+        Ok(report) => {
+            println!("{:?}", report.verdict);
+            match (&report.result, &report.target) {
+                (Some(result), _) => print_compared(result),
+                (None, Some(target)) => println!(
+                    "target exited with code {}, signal {}", target.exit_code, target.exit_signal
+                ),
+                (None, None) => println!(
+                    "validator exited with code {}, signal {}",
+                    report.validator.exit_code, report.validator.exit_signal
+                ),
+            }
+        },
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// `validate <config.toml> <tests>`: the config holds the `validator` task,
+/// which is run on every `.in` file under the tests directory.
+fn validate_inputs(args: &[String]) {
+    let (config, tests) = match (args.first().map(|x| load_config(x)), args.get(1)) {
+        (Some(Ok(x)), Some(tests)) => (x, tests),
+        (Some(Err(e)), _) => return eprintln!("{}", e),
+        _ => return eprintln!("usage: validate <config.toml> <tests>"),
+    };
+    let inputs = match test_inputs(tests) {
+        Ok(x) => x,
+        Err(e) => return eprintln!("{}", e),
+    };
+    Eval!(use nsjail with config, as jail);
+    match validate(&mut jail, "validator", &inputs) {
+        Ok(report) => {
+            for failure in &report.failures {
+                let name = match &failure.input.subtask {
+                    Some(subtask) => format!("{} (subtask {})", failure.input.path, subtask),
+                    None => failure.input.path.clone(),
+                };
+                println!("{}: rejected with exit code {}, signal {}", name, failure.exit_code, failure.exit_signal);
+                if !failure.message.is_empty() {
+                    println!("{}", failure.message);
+                }
+            }
+            println!("{} of {} input(s) valid", report.checked - report.failures.len(), report.checked);
+        },
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+/// `answers <config.toml> <tests> [source] [--cache=<dir>]`: the config
+/// holds the `compile` and `reference` tasks; `{source}` is set to the
+/// source file if given. The inputs are validated first when the config has
+/// a `validator` task.
+fn generate(args: &[String]) {
+    let (args, options) = parse_args(args);
+    let (config, tests) = match (args.first().map(|x| load_config(x)), args.get(1)) {
+        (Some(Ok(x)), Some(tests)) => (x, tests),
+        (Some(Err(e)), _) => return eprintln!("{}", e),
+        _ => return eprintln!("usage: answers <config.toml> <tests> [source] [--cache=<dir>]"),
+    };
+    let inputs = match test_inputs(tests) {
+        Ok(x) => x,
+        Err(e) => return eprintln!("{}", e),
+    };
+    let answer_config = AnswerConfig {
+        validator: config.task_config.iter().any(|x| x.name == "validator").then(|| "validator".to_string()),
+        cache: Some(CacheConfig::new(options.get("cache").unwrap_or(&DEFAULT_CACHE_PATH).to_string())),
+        ..AnswerConfig::default()
+    };
+    Eval!(use nsjail with config, as jail);
+    if let Some(source) = args.get(2) {
+        jail.set_var("source", source);
+    }
+    match generate_answers(&mut jail, &answer_config, &inputs) {
+        Ok(records) => {
+            for record in &records {
+                println!("{} -> {}: {} ms, memory {}", record.input.path, record.answer_path, (record.time * 1000.0) as i32, record.memory);
+            }
+            let time = records.iter().map(|x| x.time).fold(0.0, f32::max);
+            let memory = records.iter().map(|x| x.memory).max().unwrap_or(0);
+            println!("{} answer(s), max {} ms, memory {}", records.len(), (time * 1000.0) as i32, memory);
+        },
+        Err(e) => eprintln!("{}", e),
+    }
 }
 
 fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    match args.get(1).map(|x| x.as_str()) {
+        Some("cache-stats") => cache_stats(&args[2..]),
+        Some("stress") => stress_test(&args[2..]),
+        Some("hack") => hack_test(&args[2..]),
+        Some("validate") => validate_inputs(&args[2..]),
+        Some("answers") => generate(&args[2..]),
+        Some("manifest") => store_manifest(&args[2..]),
+        Some("diff") => show_diff(&args[2..]),
+        _ => judge(),
+    }
+}
+
+fn judge() {
+    let (removed, errors) = sweep_stale_mounts();
+    removed.iter().for_each(|x| LOGS.lock().unwrap().push(
+        format!("Successfully Removed Stale Mount: {}", x)
+    ));
+    errors.iter().for_each(|e| ERRORS.lock().unwrap().push(e.to_string()));
+
+    let mut template = TmpfsConfig::default();
+    match std::env::var("JCODER_TMPFS_HUGE").map(|x| x.parse::<HugePages>()) {
+        Ok(Ok(huge)) => template.options.huge = Some(huge),
+        Ok(Err(e)) => ERRORS.lock().unwrap().push(e.to_string()),
+        Err(_) => (),
+    };
+
+    // Problem data is either a directory or an archive of one.
+    let data = std::env::var("JCODER_DATA").unwrap_or("/home/satan/OJ/JCoder_language/test".to_string());
+
+    // Pooled workspaces are wiped when they are handed back, so a kept
+    // workspace gets a tmpfs of its own.
+    if *KEEPDIRS {
+        Eval!(create tmpfs with TmpfsConfig { path: "/tmp/test1".to_string(), ..template.clone() }, as x);
+        Eval!(create tmpfs with TmpfsConfig { path: "/tmp/test2".to_string(), ..template }, as o);
+        judge_in(&data, x, o);
+    } else {
+        let pool_config = TmpfsPoolConfig {
+            capacity: 2,
+            template,
+            ..TmpfsPoolConfig::new(format!("/tmp/jcoder_pool/{}", std::process::id()))
+        };
+        Eval!(create pool with pool_config, as pool);
+        if let Some(pool) = pool {
+            Eval!(acquire x from pool);
+            Eval!(acquire o from pool);
+            if let (Some(x), Some(o)) = (x, o) {
+                judge_in(&data, x, o);
+            }
+            Eval!(close pool);
+        }
+    }
 
+    println!("{:?}", LOGS.lock().unwrap());
+    println!("{:?}", ERRORS.lock().unwrap());
+}
+
+/// Judges with the problem data at `data` copied into `x` and the task
+/// outputs written to `o`. Data without a manifest is judged unverified.
+fn judge_in(data: &str, x: Arc<Mutex<dyn BaseDir + Send + Sync>>, o: Arc<Mutex<dyn BaseDir + Send + Sync>>) {
     let mut config = NsjailConfig::default();
     let mut task = NsjailTask::default();
     // task.config.insert("time_limit".to_string(), "1000".to_string());
@@ -182,27 +640,98 @@ fn main() {
     task.config.insert("log".to_string(), "test.log".to_string());
     task.config.insert("stdout".to_string(), "test.out".to_string());
     task.config.insert("report".to_string(), "test.rep".to_string());
-    task.cwd = "/oj".to_string();
+    task.cwd = "/test".to_string();
+    task.name = "compile".to_string();
     task.exec = "/usr/bin/g++".to_string();
-    task.args = vec!["test.cpp".to_string(), "-o".to_string(), "a.out".to_string()];
+    task.args = vec!["{source}".to_string(), "-o".to_string(), "{binary}".to_string()];
+    task.outputs.insert("binary".to_string(), "{binary}".to_string());
+    task.cache = vec!["{host:/test}/{source}".to_string()];
     config.task_config.push(task.clone());
 
-    task.exec = "./a.out".to_string();
+    task.name = "run".to_string();
+    task.exec = "{compile.binary}".to_string();
     task.args.clear();
+    task.outputs.clear();
+    task.cache.clear();
     config.task_config.push(task.clone());
 
     config.rootfs_path = "/".to_string();
 
     Eval!(use nsjail with config, as jail);
-    Eval!(use tmpfs "/tmp/test1", as x);
-    Eval!(use localfs "/home/satan/OJ/JCoder_language/test", as y);
-    Eval!(copydir y to x);
+    jail.use_cache(&CacheConfig::new(DEFAULT_CACHE_PATH.to_string()));
+    if PathBuf::from(data).is_file() {
+        Eval!(extract data, to x);
+        Eval!(verify x);
+    } else {
+        Eval!(use localfs data, as y);
+        Eval!(verify y);
+        Eval!(copydir y to x);
+        Eval!(verify x against y);
+    }
+    let workspace = x.lock().unwrap().get_src();
     Eval!(mount x to jail at "/test");
+    Eval!(write outputs of jail to o);
+    Eval!(set "source" to "test.cpp", in jail);
+    Eval!(set "binary" to "a.out", in jail);
+    Eval!(set "testcase_id" to 1, in jail);
     Eval!(run all tasks in jail as result);
-    Eval!(compare file "test/1.txt".to_string(), to result[1].output_path, as cmp);
+    Eval!(show files of result);
+    let mut verdict = BaseResult::default();
+    let failed = *STATUS.lock().unwrap() == BaseStatus::SE;
+    match result.get(1).and_then(|x| x.as_ref()) {
+        _ if failed => (),
+        Some(run) => {
+            Eval!(update result verdict by judger {run});
+            // A test case has a single answer `1.txt`, or a directory `1`
+            // holding every accepted answer.
+            let case = format!("{}/1", workspace);
+            let case = if PathBuf::from(&case).is_dir() { case } else { format!("{}.txt", case) };
+            match answer_files(&case) {
+                Ok(answers) => {
+                    Eval!(judge files answers, to run.output_path, with CompareConfig::default(), as cmp);
+                    if let Ok(ref compared) = cmp {
+                        Eval!(update result verdict by compare {compared});
+                    }
+                    Eval!(show diff of verdict);
+                },
+                Err(e) => {
+                    ERRORS.lock().unwrap().push(e.to_string());
+                    *STATUS.lock().unwrap() = BaseStatus::SE;
+                },
+            };
+        },
+        None => ERRORS.lock().unwrap().push("Task run was skipped".to_string()),
+    };
     Eval!(clean);
-
-    println!("{:?}", LOGS.lock().unwrap());
-    println!("{:?}", ERRORS.lock().unwrap());
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch;
+
+    fn workspace(name: &str) -> Arc<Mutex<dyn BaseDir + Send + Sync>> {
+        Arc::new(Mutex::new(scratch(name)))
+    }
+
+    #[test]
+    fn judge_in_skips_verification_without_a_manifest() {
+        let data = scratch("judge_data");
+        std::fs::write(data.src.join("1.txt"), b"3\n").unwrap();
+        LOGS.lock().unwrap().clear();
+        ERRORS.lock().unwrap().clear();
+        judge_in(&data.get_src(), workspace("judge_x"), workspace("judge_o"));
+        let skipped = LOGS.lock().unwrap().iter()
+            .filter(|x| x.starts_with("Warning: Skipped Verifying Directory Without Manifest"))
+            .count();
+        assert_eq!(skipped, 2);
+        assert!(ERRORS.lock().unwrap().iter().all(|x| !x.starts_with("Integrity check failed")));
+
+        Manifest::compute(&*data).unwrap().store(&*data).unwrap();
+        std::fs::write(data.src.join("1.txt"), b"4\n").unwrap();
+        ERRORS.lock().unwrap().clear();
+        judge_in(&data.get_src(), workspace("judge_x"), workspace("judge_o"));
+        assert!(ERRORS.lock().unwrap().iter().any(|x| x.starts_with("Integrity check failed")));
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::error::Error;
+use crate::nsjail_judger::cache::CacheConfig;
+use crate::nsjail_judger::judger::NsjailJudger;
+use crate::nsjail_judger::validate::{validate, TestInput};
+
+/// Builds the reference solution with the `compile` task and runs the
+/// `reference` task on every input through `stdin = "{input}"`.
+#[derive(Clone)]
+pub struct AnswerConfig {
+    pub compile: String,
+    pub reference: String,
+    /// Task run on every input before any answer is written; one rejected
+    /// input fails the whole generation.
+    pub validator: Option<String>,
+    /// nsjail options overriding those of the reference task, so that the
+    /// limits of the problem do not cut the reference solution short. The
+    /// defaults lift the time limit and every memory limit.
+    pub limits: HashMap<String, String>,
+    /// The answer of `1.in` is written next to it as `1.{extension}`.
+    pub extension: String,
+    /// Where the compiled reference solution is cached, if anywhere.
+    pub cache: Option<CacheConfig>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnswerRecord {
+    pub input: TestInput,
+    pub answer_path: String,
+    pub time: f32,
+    pub memory: i32,
+}
+
+/// Writes the answer of every input and records the time and memory the
+/// reference solution took. A rejected input, a failing compile or a
+/// failing run is an error.
+pub fn generate_answers(jail: &mut NsjailJudger, config: &AnswerConfig, inputs: &[TestInput]) -> Result<Vec<AnswerRecord>, Error> {
+    if let Some(validator) = &config.validator {
+        let report = validate(jail, validator, inputs)?;
+        if !report.is_valid() {
+            let failure = &report.failures[0];
+            return Err(Error::Integrity {
+                path: failure.input.path.clone().into(),
+                reason: format!(
+                    "{} of {} input(s) rejected by {}, exit code {}, signal {}: {}",
+                    report.failures.len(), report.checked, validator,
+                    failure.exit_code, failure.exit_signal, failure.message
+                ),
+            });
+        }
+    }
+    let task = jail.config.task_config.iter_mut()
+        .find(|x| x.name == config.reference)
+        .ok_or_else(|| Error::config(&config.reference, "no such task"))?;
+    task.config.extend(config.limits.clone());
+    if let Some(cache) = &config.cache {
+        jail.use_cache(cache);
+    }
+    jail.run_accepted(&config.compile)?;
+    let mut records = vec![];
+    for input in inputs {
+        jail.set_var("input", &input.path);
+        let result = jail.run_accepted(&config.reference)?;
+        let answer_path = Path::new(&input.path).with_extension(&config.extension).to_string_lossy().to_string();
+        fs::copy(&result.output_path, &answer_path).map_err(|e| Error::io(&answer_path, e))?;
+        records.push(AnswerRecord {
+            input: input.clone(),
+            answer_path,
+            time: result.time,
+            memory: result.memory,
+        });
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nsjail_judger::cache::DEFAULT_CACHE_PATH;
+    use crate::nsjail_judger::judger::{NsjailConfig, NsjailTask};
+
+    fn jail(names: &[&str]) -> NsjailJudger {
+        let task_config = names.iter().map(|name| NsjailTask {
+            name: name.to_string(),
+            config: HashMap::from([
+                ("time_limit".to_string(), "1".to_string()),
+                ("cgroup_mem_max".to_string(), "268435456".to_string()),
+                ("hostname".to_string(), "jcoder".to_string()),
+            ]),
+            ..NsjailTask::default()
+        }).collect();
+        NsjailJudger::new(&NsjailConfig { task_config, ..NsjailConfig::default() })
+    }
+
+    #[test]
+    fn defaults_lift_every_limit_and_cache_the_build() {
+        let config = AnswerConfig::default();
+        assert_eq!(config.limits["time_limit"], "60");
+        assert_eq!(config.limits["rlimit_as"], "inf");
+        assert_eq!(config.limits["cgroup_mem_max"], "0");
+        assert_eq!(config.limits["cgroup_mem_memsw_max"], "0");
+        assert_eq!(config.limits["cgroup_mem_swap_max"], "-1");
+        assert_eq!(config.cache.unwrap().path, DEFAULT_CACHE_PATH);
+        assert!(config.validator.is_none());
+    }
+
+    #[test]
+    fn limits_override_only_the_reference_task() {
+        let mut jail = jail(&["reference", "other"]);
+        let config = AnswerConfig { cache: None, ..AnswerConfig::default() };
+        assert!(matches!(generate_answers(&mut jail, &config, &[]), Err(Error::Config { path, .. }) if path.as_os_str() == "compile"));
+        let reference = &jail.config.task_config[0].config;
+        assert_eq!(reference["time_limit"], "60");
+        assert_eq!(reference["cgroup_mem_max"], "0");
+        assert_eq!(reference["hostname"], "jcoder");
+        assert_eq!(jail.config.task_config[1].config["time_limit"], "1");
+    }
+
+    #[test]
+    fn missing_reference_is_a_config_error() {
+        let mut jail = jail(&["compile"]);
+        let config = AnswerConfig { validator: Some("validator".to_string()), ..AnswerConfig::default() };
+        assert!(matches!(generate_answers(&mut jail, &config, &[]), Err(Error::Config { path, .. }) if path.as_os_str() == "reference"));
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::error::Error;
+use crate::nsjail_judger::judger::NsjailTask;
+
+/// Cache directory used when none is configured.
+pub const DEFAULT_CACHE_PATH: &str = "/tmp/jcoder_cache";
+
+const ENTRY_FILE: &str = "entry.toml";
+const STATS_FILE: &str = "stats.toml";
+const STDOUT_FILE: &str = "stdout";
+const REPORT_FILE: &str = "report";
+const ARTIFACT_DIR: &str = "artifacts";
+
+#[derive(Clone)]
+pub struct CacheConfig {
+    pub path: String,
+    pub max_size: u64,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub struct CacheEntry {
+    pub key: String,
+    pub size: u64,
+    pub created: u64,
+    pub last_used: u64,
+    pub artifacts: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    #[serde(skip)]
+    pub entries: usize,
+    #[serde(skip)]
+    pub size: u64,
+}
+
+pub struct CompileCache {
+    pub config: CacheConfig,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0)
+}
+
+fn dir_size(path: &Path) -> u64 {
+    fs_extra::dir::get_size(path).unwrap_or(0)
+}
+
+impl CompileCache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Self { config: config.clone() }
+    }
+
+    /// Hashes the content of the source files together with everything that
+    /// affects the produced binary: the language profile (`meta_data`), the
+    /// compiler invocation and environment, and the rootfs.
+    pub fn key(sources: &[String], task: &NsjailTask, rootfs_id: &str) -> Result<String, Error> {
+        let mut hasher = Sha256::new();
+        for source in sources {
+            let content = fs::read(source).map_err(|e| Error::io(source, e))?;
+            hasher.update((content.len() as u64).to_le_bytes());
+            hasher.update(&content);
+        }
+        let mut profile = task.meta_data.iter().collect::<Vec<_>>();
+        profile.sort();
+        let mut envs = task.envs.iter().collect::<Vec<_>>();
+        envs.sort();
+        let fields = profile.iter().chain(envs.iter())
+            .map(|(k, v)| format!("{}={}", k, v))
+            .chain(std::iter::once(task.exec.clone()))
+            .chain(task.args.iter().cloned())
+            .chain(std::iter::once(rootfs_id.to_string()));
+        for field in fields {
+            hasher.update((field.len() as u64).to_le_bytes());
+            hasher.update(field.as_bytes());
+        }
+        Ok(hasher.finalize().iter().map(|x| format!("{:02x}", x)).collect())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        Path::new(&self.config.path).join(key)
+    }
+
+    fn read_entry(path: &Path) -> Option<CacheEntry> {
+        let content = fs::read_to_string(path.join(ENTRY_FILE)).ok()?;
+        toml::from_str(&content).ok()
+    }
+
+    fn write_entry(path: &Path, entry: &CacheEntry) -> Result<(), Error> {
+        let path = path.join(ENTRY_FILE);
+        let content = toml::to_string(entry).map_err(|e| Error::config(&path, e))?;
+        fs::write(&path, content).map_err(|e| Error::io(&path, e))
+    }
+
+    fn read_stats(&self) -> CacheStats {
+        fs::read_to_string(Path::new(&self.config.path).join(STATS_FILE)).ok()
+            .and_then(|x| toml::from_str::<CacheStats>(&x).ok())
+            .unwrap_or_default()
+    }
+
+    fn update_stats(&self, f: impl FnOnce(&mut CacheStats)) {
+        let mut stats = self.read_stats();
+        f(&mut stats);
+        if let Ok(content) = toml::to_string(&stats) {
+            let _ = fs::create_dir_all(&self.config.path);
+            let _ = fs::write(Path::new(&self.config.path).join(STATS_FILE), content);
+        }
+    }
+
+    /// Copies the cached artifacts to their host paths and the compiler
+    /// output to `out_path`/`rep_path`. Returns `false` on a miss, which
+    /// includes an entry that does not hold exactly the requested artifacts
+    /// or lost one of its files.
+    pub fn restore(&self, key: &str, artifacts: &HashMap<String, String>, out_path: &str, rep_path: &str) -> Result<bool, Error> {
+        let path = self.entry_path(key);
+        let complete = |entry: &CacheEntry| {
+            entry.artifacts.len() == artifacts.len() &&
+                entry.artifacts.iter().all(|x| artifacts.contains_key(x) && path.join(ARTIFACT_DIR).join(x).is_file()) &&
+                path.join(STDOUT_FILE).is_file() &&
+                path.join(REPORT_FILE).is_file()
+        };
+        let mut entry = match CompileCache::read_entry(&path) {
+            Some(entry) if complete(&entry) => entry,
+            _ => {
+                self.update_stats(|x| x.misses += 1);
+                return Ok(false);
+            }
+        };
+        for (name, target) in artifacts {
+            fs::copy(path.join(ARTIFACT_DIR).join(name), target).map_err(|e| Error::io(target, e))?;
+        }
+        fs::copy(path.join(STDOUT_FILE), out_path).map_err(|e| Error::io(out_path, e))?;
+        fs::copy(path.join(REPORT_FILE), rep_path).map_err(|e| Error::io(rep_path, e))?;
+        entry.last_used = now();
+        CompileCache::write_entry(&path, &entry)?;
+        self.update_stats(|x| x.hits += 1);
+        Ok(true)
+    }
+
+    pub fn store(&self, key: &str, artifacts: &HashMap<String, String>, out_path: &str, rep_path: &str) -> Result<(), Error> {
+        let path = self.entry_path(key);
+        let artifact_dir = path.join(ARTIFACT_DIR);
+        fs::create_dir_all(&artifact_dir).map_err(|e| Error::io(&artifact_dir, e))?;
+        for (name, source) in artifacts {
+            fs::copy(source, artifact_dir.join(name)).map_err(|e| Error::io(source, e))?;
+        }
+        fs::copy(out_path, path.join(STDOUT_FILE)).map_err(|e| Error::io(out_path, e))?;
+        fs::copy(rep_path, path.join(REPORT_FILE)).map_err(|e| Error::io(rep_path, e))?;
+        let entry = CacheEntry {
+            key: key.to_string(),
+            size: dir_size(&path),
+            created: now(),
+            last_used: now(),
+            artifacts: artifacts.keys().cloned().collect(),
+        };
+        CompileCache::write_entry(&path, &entry)?;
+        self.evict()?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, Error> {
+        let dir = match fs::read_dir(&self.config.path) {
+            Ok(dir) => dir,
+            Err(_) => return Ok(vec![]),
+        };
+        Ok(dir.filter_map(|x| x.ok())
+            .filter(|x| x.path().is_dir())
+            .filter_map(|x| CompileCache::read_entry(&x.path()))
+            .collect())
+    }
+
+    /// Removes the least recently used entries until the cache fits in
+    /// `max_size` bytes. Returns the number of evicted entries.
+    pub fn evict(&self) -> Result<usize, Error> {
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|x| x.last_used);
+        let mut total = entries.iter().map(|x| x.size).sum::<u64>();
+        let mut evicted = 0usize;
+        for entry in entries {
+            if total <= self.config.max_size {
+                break;
+            }
+            let path = self.entry_path(&entry.key);
+            fs::remove_dir_all(&path).map_err(|e| Error::io(&path, e))?;
+            total -= entry.size;
+            evicted += 1;
+        }
+        if evicted > 0 {
+            self.update_stats(|x| x.evictions += evicted as u64);
+        }
+        Ok(evicted)
+    }
+
+    pub fn stats(&self) -> Result<CacheStats, Error> {
+        let mut stats = self.read_stats();
+        let entries = self.entries()?;
+        stats.entries = entries.len();
+        stats.size = entries.iter().map(|x| x.size).sum();
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch;
+
+    fn file(dir: &Path, name: &str, content: &str) -> String {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn cache(dir: &Path) -> CompileCache {
+        CompileCache::new(&CacheConfig::new(dir.join("cache").to_string_lossy().to_string()))
+    }
+
+    #[test]
+    fn key_depends_on_sources_and_invocation() {
+        let tmp = scratch("key");
+        let dir = &tmp.src;
+        let sources = vec![file(dir, "a.cpp", "int main() {}")];
+        let task = NsjailTask { exec: "/usr/bin/g++".to_string(), ..NsjailTask::default() };
+        let key = CompileCache::key(&sources, &task, "rootfs").unwrap();
+        assert_eq!(key, CompileCache::key(&sources, &task.clone(), "rootfs").unwrap());
+        assert_ne!(key, CompileCache::key(&sources, &task, "other").unwrap());
+        let optimized = NsjailTask { args: vec!["-O2".to_string()], ..task.clone() };
+        assert_ne!(key, CompileCache::key(&sources, &optimized, "rootfs").unwrap());
+        file(dir, "a.cpp", "int main() { return 1; }");
+        assert_ne!(key, CompileCache::key(&sources, &task, "rootfs").unwrap());
+        assert!(CompileCache::key(&[dir.join("missing.cpp").to_string_lossy().to_string()], &task, "rootfs").is_err());
+    }
+
+    #[test]
+    fn restores_what_was_stored() {
+        let tmp = scratch("roundtrip");
+        let dir = &tmp.src;
+        let cache = cache(dir);
+        let binary = file(dir, "a.out", "binary");
+        let out = file(dir, "out", "compiler output");
+        let rep = file(dir, "rep", "report");
+        let artifacts = HashMap::from([("binary".to_string(), binary.clone())]);
+        assert!(!cache.restore("k", &artifacts, &out, &rep).unwrap());
+        cache.store("k", &artifacts, &out, &rep).unwrap();
+        for path in [&binary, &out, &rep] {
+            fs::remove_file(path).unwrap();
+        }
+        assert!(cache.restore("k", &artifacts, &out, &rep).unwrap());
+        assert_eq!(fs::read_to_string(&binary).unwrap(), "binary");
+        assert_eq!(fs::read_to_string(&out).unwrap(), "compiler output");
+        let stats = cache.stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+    }
+
+    #[test]
+    fn different_artifacts_are_a_miss() {
+        let tmp = scratch("artifacts");
+        let dir = &tmp.src;
+        let cache = cache(dir);
+        let out = file(dir, "out", "");
+        let rep = file(dir, "rep", "");
+        let binary = HashMap::from([("binary".to_string(), file(dir, "a.out", "binary"))]);
+        cache.store("k", &binary, &out, &rep).unwrap();
+        assert!(!cache.restore("k", &HashMap::new(), &out, &rep).unwrap());
+        let more = HashMap::from([
+            ("binary".to_string(), dir.join("a.out").to_string_lossy().to_string()),
+            ("debug".to_string(), dir.join("a.dbg").to_string_lossy().to_string()),
+        ]);
+        assert!(!cache.restore("k", &more, &out, &rep).unwrap());
+        let renamed = HashMap::from([("program".to_string(), dir.join("a.out").to_string_lossy().to_string())]);
+        assert!(!cache.restore("k", &renamed, &out, &rep).unwrap());
+    }
+
+    #[test]
+    fn lost_artifact_is_a_miss() {
+        let tmp = scratch("lost");
+        let dir = &tmp.src;
+        let cache = cache(dir);
+        let out = file(dir, "out", "");
+        let rep = file(dir, "rep", "");
+        let artifacts = HashMap::from([("binary".to_string(), file(dir, "a.out", "binary"))]);
+        cache.store("k", &artifacts, &out, &rep).unwrap();
+        fs::remove_file(cache.entry_path("k").join(ARTIFACT_DIR).join("binary")).unwrap();
+        assert!(!cache.restore("k", &artifacts, &out, &rep).unwrap());
+    }
+
+    #[test]
+    fn evicts_down_to_max_size() {
+        let tmp = scratch("evict");
+        let dir = &tmp.src;
+        let cache = CompileCache::new(&CacheConfig {
+            max_size: 0,
+            ..cache(dir).config
+        });
+        let out = file(dir, "out", "output");
+        let rep = file(dir, "rep", "report");
+        cache.store("k", &HashMap::new(), &out, &rep).unwrap();
+        let stats = cache.stats().unwrap();
+        assert_eq!((stats.entries, stats.evictions), (0, 1));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::compare::compare::{judge_file, CompareConfig};
+use crate::compare::results::{BaseStatus, CompareResult};
+use crate::error::Error;
+use crate::nsjail_judger::judger::NsjailJudger;
+use crate::nsjail_judger::result::JudgerResult;
+
+/// Runs the `validator`, `reference` and `target` tasks on the input at
+/// `input_path`, which they read through `stdin = "{input}"`.
+#[derive(Clone)]
+pub struct HackConfig {
+    pub validator: String,
+    pub reference: String,
+    pub target: String,
+    pub input_path: String,
+    pub compare: CompareConfig,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HackVerdict {
+    /// The validator rejected the input.
+    Invalid,
+    /// The target solved the input.
+    Unsuccessful,
+    /// The target failed or gave a wrong answer.
+    Successful,
+}
+
+pub struct HackReport {
+    pub verdict: HackVerdict,
+    pub validator: JudgerResult,
+    pub target: Option<JudgerResult>,
+    /// The comparison, if the target exited cleanly.
+    pub result: Option<CompareResult>,
+}
+
+/// Judges the target on a hack. An input the validator rejects is
+/// `Invalid` and nothing else runs; a failing reference is an error.
+pub fn hack(jail: &mut NsjailJudger, config: &HackConfig) -> Result<HackReport, Error> {
+    jail.set_var("input", &config.input_path);
+    let (validator, valid) = jail.run_task(&config.validator)?;
+    if !valid {
+        return Ok(HackReport { verdict: HackVerdict::Invalid, validator, target: None, result: None });
+    }
+    let answer = jail.run_accepted(&config.reference)?;
+    let (target, accepted) = jail.run_task(&config.target)?;
+    let result = match accepted {
+        true => Some(judge_file(&answer.output_path, &target.output_path, &config.compare)?),
+        false => None,
+    };
+    let verdict = match &result {
+        Some(x) if x.status == BaseStatus::AC => HackVerdict::Unsuccessful,
+        _ => HackVerdict::Successful,
+    };
+    Ok(HackReport { verdict, validator, target: Some(target), result })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nsjail_judger::judger::NsjailConfig;
+
+    #[test]
+    fn missing_validator_is_a_config_error() {
+        let mut jail = NsjailJudger::new(&NsjailConfig::default());
+        let config = HackConfig { input_path: "/hacks/1.in".to_string(), ..HackConfig::default() };
+        assert!(matches!(hack(&mut jail, &config), Err(Error::Config { path, .. }) if path.as_os_str() == "validator"));
+        assert_eq!(jail.context()["input"], "/hacks/1.in");
+    }
+}
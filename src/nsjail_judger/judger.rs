@@ -1,11 +1,28 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use serde::{Deserialize, Serialize};
+use crate::dir_utils::tmpfs::{disk_usage, DiskUsage};
 use crate::dir_utils::traits::BaseDir;
 use std::process::{Command};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 use random_string::generate;
-use crate::nsjail_judger::result::JudgerResult;
+use crate::error::Error;
+use crate::nsjail_judger::cache::{CacheConfig, CompileCache};
+use crate::nsjail_judger::result::{CollectedFile, JudgerResult};
+use crate::nsjail_judger::template::render;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RunCondition {
+    Always,
+    #[default]
+    OnSuccess,
+    OnFailure,
+}
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct NsjailTask {
@@ -15,22 +32,62 @@ pub struct NsjailTask {
     pub exec: String,
     pub args: Vec<String>,
     pub cwd: String,
-    pub envs: HashMap<String, String>
+    pub envs: HashMap<String, String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub run_if: RunCondition,
+    #[serde(default)]
+    pub allow_nonzero: bool,
+    #[serde(default)]
+    pub retry: u32,
+    #[serde(default)]
+    pub outputs: HashMap<String, String>,
+    #[serde(default)]
+    pub cache: Vec<String>,
+    #[serde(default)]
+    pub collect: Vec<String>,
+    #[serde(default = "default_collect_limit")]
+    pub collect_limit: u64,
+    /// Host path of a file fed to the standard input, e.g.
+    /// `{host:generator.stdout}`.
+    #[serde(default)]
+    pub stdin: String,
+    /// Host path of a file receiving the standard error.
+    #[serde(default)]
+    pub stderr: String,
+}
+
+/// How often a running task is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// How often the disk usage of the mounted directories is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+/// Messages of ENOSPC and EDQUOT, as printed by `strerror`.
+const DISK_FULL_MESSAGES: [&str; 2] = ["No space left on device", "Disk quota exceeded"];
+
+pub fn default_collect_limit() -> u64 {
+    16 << 20
 }
 
 #[derive(Deserialize, Serialize, Default, Clone)]
 pub struct NsjailConfig {
     pub rootfs_path: String,
+    #[serde(default)]
+    pub rootfs_id: String,
     pub task_config: Vec<NsjailTask>,
 }
 
 pub struct NsjailJudger {
     pub config: NsjailConfig,
     pub dirs: Vec<Arc<Mutex<dyn BaseDir>>>,
-    pub user_dir: i32,
     pub output_dir: i32,
     pub cursor: usize,
-    pub prefix: String
+    pub prefix: String,
+    pub outputs: HashMap<String, String>,
+    pub variables: HashMap<String, String>,
+    pub mount_points: HashMap<String, String>,
+    pub cache: Option<CompileCache>,
+    pub failed: bool,
 }
 
 impl NsjailJudger {
@@ -38,14 +95,19 @@ impl NsjailJudger {
         NsjailJudger {
             config: config.clone(),
             dirs: vec![],
-            user_dir: -1,
             output_dir: -1,
             cursor: 0,
-            prefix: generate(6, "abcdefghigklmnopqrstuvwxyz")
+            prefix: generate(6, "abcdefghigklmnopqrstuvwxyz"),
+            outputs: HashMap::new(),
+            variables: HashMap::new(),
+            mount_points: HashMap::new(),
+            cache: None,
+            failed: false,
         }
     }
     pub fn mount_all(&mut self, dir: Arc<Mutex<dyn BaseDir>>, target: &String) {
         self.dirs.push(dir.clone());
+        self.mount_points.insert(target.clone(), dir.lock().unwrap().get_src());
         self.config.task_config.iter_mut().for_each(|task| {
             task.mount.push(dir.lock().unwrap().get_bind_string(target));
         });
@@ -85,56 +147,548 @@ impl NsjailJudger {
         (out_path, rep_path)
     }
 
-    pub fn run(&mut self, cursor: i32) -> JudgerResult {
-        let mut task = &mut self.config.task_config[cursor as usize];
-        let dir = ".".to_string();
-        let (out_path, rep_path) = NsjailJudger::convert_task(&dir, task, &self.prefix, self.cursor);
+    /// Writes the stdout, report and log files of the tasks into `dir`
+    /// instead of the working directory. The directory is not mounted into
+    /// the sandbox.
+    pub fn use_output_dir(&mut self, dir: Arc<Mutex<dyn BaseDir>>) {
+        self.output_dir = self.dirs.len() as i32;
+        self.dirs.push(dir);
+    }
+
+    pub fn use_cache(&mut self, config: &CacheConfig) {
+        self.cache = Some(CompileCache::new(config));
+    }
+
+    pub fn set_var(&mut self, key: &str, value: &str) {
+        self.variables.insert(key.to_string(), value.to_string());
+    }
+
+    /// Collects everything a task template may reference: per-run variables,
+    /// the outputs recorded earlier in the pipeline and the mounted
+    /// directories. Paths inside the sandbox are `{task.output}` and
+    /// `{mount:/target}`; their host paths are `{host:task.output}` and
+    /// `{host:/target}`. The stdout, report and stderr files of a task only
+    /// exist on the host, as `{host:task.stdout}` and so on.
+    pub fn context(&self) -> HashMap<String, String> {
+        let mut context = self.variables.clone();
+        context.extend(self.outputs.iter().map(|(k, v)| (k.clone(), v.clone())));
+        for (target, src) in &self.mount_points {
+            context.insert(format!("mount:{}", target), target.clone());
+            context.insert(format!("host:{}", target), src.clone());
+        }
+        context
+    }
+
+    pub fn render_task(&self, task: &mut NsjailTask) {
+        let context = self.context();
+        task.exec = render(&task.exec, &context);
+        task.cwd = render(&task.cwd, &context);
+        task.args.iter_mut().for_each(|x| *x = render(x, &context));
+        task.envs.values_mut().for_each(|x| *x = render(x, &context));
+        task.config.values_mut().for_each(|x| *x = render(x, &context));
+        task.outputs.values_mut().for_each(|x| *x = render(x, &context));
+        task.cache.iter_mut().for_each(|x| *x = render(x, &context));
+        task.collect.iter_mut().for_each(|x| *x = render(x, &context));
+        task.stdin = render(&task.stdin, &context);
+        task.stderr = render(&task.stderr, &context);
+    }
+
+    /// Finds the mount point containing the sandbox path `path` and returns
+    /// its target and host source.
+    fn mount_of(&self, path: &str) -> Option<(&str, &str)> {
+        self.mount_points.iter()
+            .map(|(target, src)| (target.trim_end_matches('/'), src.as_str()))
+            .filter(|(target, _)| path == *target || path.starts_with(&format!("{}/", target)))
+            .max_by_key(|(target, _)| target.len())
+    }
+
+    /// Maps a path inside the sandbox to the host path of the directory
+    /// mounted there.
+    pub fn host_path(&self, path: &str) -> Option<String> {
+        self.mount_of(path).map(|(target, src)| format!("{}{}", src, &path[target.len()..]))
+    }
+
+    fn absolute(task: &NsjailTask, path: &str) -> String {
+        if Path::new(path).is_absolute() {
+            return path.to_string();
+        }
+        format!("{}/{}", task.cwd.trim_end_matches('/'), path)
+    }
+
+    fn output_paths(task: &NsjailTask) -> HashMap<String, String> {
+        task.outputs.iter()
+            .map(|(key, path)| (key.clone(), NsjailJudger::absolute(task, path)))
+            .collect()
+    }
 
-        let result = Command::new("./nsjail")
-            .args(&task.to_args(&self.config.rootfs_path))
-            .status();
-        if result.is_ok() {
-            return JudgerResult::from_file(&rep_path, &out_path);
+    /// Reads the files matching the `collect` globs of a task, up to
+    /// `collect_limit` bytes in total. Symlinks and files resolving outside
+    /// the mounted directory are skipped; a file that cannot be read is
+    /// reported as truncated with no data.
+    pub fn collect_files(&self, task: &NsjailTask) -> Result<Vec<CollectedFile>, Error> {
+        let mut files = vec![];
+        let mut budget = task.collect_limit;
+        for pattern in &task.collect {
+            let pattern = NsjailJudger::absolute(task, pattern);
+            let (target, src) = match self.mount_of(&pattern) {
+                Some(x) => x,
+                None => return Err(Error::config(&pattern, "not inside a mounted directory")),
+            };
+            let root = Path::new(src).canonicalize().map_err(|e| Error::io(src, e))?;
+            let host = format!("{}{}", src, &pattern[target.len()..]);
+            let paths = glob::glob(&host).map_err(|e| Error::config(&pattern, e))?;
+            for path in paths.filter_map(|x| x.ok()) {
+                let size = match path.symlink_metadata() {
+                    Ok(metadata) if metadata.is_file() => metadata.len(),
+                    _ => continue,
+                };
+                match path.canonicalize() {
+                    Ok(real) if real.starts_with(&root) => (),
+                    _ => continue,
+                }
+                let mut data = vec![];
+                let truncated = match File::open(&path).and_then(|x| x.take(budget).read_to_end(&mut data)) {
+                    Ok(_) => (data.len() as u64) < size,
+                    Err(_) => {
+                        data.clear();
+                        true
+                    },
+                };
+                budget -= data.len() as u64;
+                let relative = path.strip_prefix(src).unwrap_or(&path).to_string_lossy().to_string();
+                files.push(CollectedFile {
+                    path: format!("{}/{}", target, relative.trim_start_matches('/')),
+                    truncated,
+                    size,
+                    data,
+                });
+            }
         }
-        JudgerResult::default()
+        Ok(files)
     }
 
-    #[cfg(online)]
-    pub fn run(&mut self, cursor: i32) -> JudgerResult {
-        let mut task = &mut self.config.task_config[cursor as usize];
-        let out = self.output_dir;
-        if out < 0 {
-            return JudgerResult::default();
+    fn record_outputs(&mut self, task: &NsjailTask, result: &JudgerResult) {
+        if task.name.is_empty() {
+            return;
         }
-        let dir = self.dirs[out as usize].lock().unwrap().get_src();
-        let (out_path, rep_path) = NsjailJudger::convert_task(&dir, task, &self.prefix, self.cursor);
-        let result = Command::new("./nsjail")
-            .args(&task.to_args(&self.config.rootfs_path))
-            .status();
-        if result.is_ok() {
-            return JudgerResult::from_file(&rep_path, &out_path);
+        self.outputs.insert(format!("host:{}.stdout", task.name), result.output_path.clone());
+        self.outputs.insert(format!("host:{}.report", task.name), result.report_path.clone());
+        if !task.stderr.is_empty() {
+            self.outputs.insert(format!("host:{}.stderr", task.name), task.stderr.clone());
+        }
+        for (key, path) in NsjailJudger::output_paths(task) {
+            if let Some(host) = self.host_path(&path) {
+                self.outputs.insert(format!("host:{}.{}", task.name, key), host);
+            }
+            self.outputs.insert(format!("{}.{}", task.name, key), path);
         }
-        JudgerResult::default()
     }
 
-    pub fn run_step(&mut self) -> JudgerResult {
+    fn sample_usage(paths: &[String], usage: &mut HashMap<String, DiskUsage>) {
+        for path in paths {
+            if let Ok(current) = disk_usage(Path::new(path)) {
+                usage.entry(path.clone()).or_default().merge(&current);
+            }
+        }
+    }
+
+    /// Spawns nsjail and samples the disk usage of the mounted directories
+    /// until it exits. The peaks only cover this run. The directories are
+    /// only locked to find the filesystems to sample, not while sampling.
+    fn spawn(&self, task: &NsjailTask) -> Result<HashMap<String, DiskUsage>, Error> {
+        let spawn_error = |e| Error::Spawn { exec: task.exec.clone(), source: e };
+        let mut usage = HashMap::new();
+        let mut paths = vec![];
+        for dir in &self.dirs {
+            let mut dir = dir.lock().unwrap();
+            dir.reset_usage();
+            if let Some(current) = dir.usage() {
+                usage.insert(dir.get_src(), current);
+                paths.push(dir.get_src());
+            }
+        }
+        let mut command = Command::new("./nsjail");
+        command.args(task.to_args(&self.config.rootfs_path));
+        if !task.stdin.is_empty() {
+            command.stdin(File::open(&task.stdin).map_err(|e| Error::io(&task.stdin, e))?);
+        }
+        if !task.stderr.is_empty() {
+            command.stderr(File::create(&task.stderr).map_err(|e| Error::io(&task.stderr, e))?);
+        }
+        let mut child = command
+            .spawn()
+            .map_err(spawn_error)?;
+        let mut sampled = Instant::now();
+        while child.try_wait().map_err(spawn_error)?.is_none() {
+            if sampled.elapsed() >= SAMPLE_INTERVAL {
+                NsjailJudger::sample_usage(&paths, &mut usage);
+                sampled = Instant::now();
+            }
+            sleep(POLL_INTERVAL);
+        }
+        NsjailJudger::sample_usage(&paths, &mut usage);
+        Ok(usage)
+    }
+
+    fn log_length(task: &NsjailTask) -> u64 {
+        task.config.get("log")
+            .and_then(|x| std::fs::metadata(x).ok())
+            .map_or(0, |x| x.len())
+    }
+
+    fn mentions_disk_full(path: &str, start: u64) -> bool {
+        let mut data = vec![];
+        let read = File::open(path)
+            .and_then(|mut x| x.seek(SeekFrom::Start(start)).map(|_| x))
+            .and_then(|mut x| x.read_to_end(&mut data));
+        let text = String::from_utf8_lossy(&data);
+        read.is_ok() && DISK_FULL_MESSAGES.iter().any(|x| text.contains(x))
+    }
+
+    /// Whether the run reported ENOSPC or EDQUOT, either as its exit status
+    /// or in its standard error or the part of the nsjail log written since
+    /// `log_start`. Unlike the sampled usage, this cannot miss a file that
+    /// was written and removed between two samples.
+    fn reports_disk_full(task: &NsjailTask, log_start: u64, result: &JudgerResult) -> bool {
+        if result.exit_signal == 0 && [libc::ENOSPC, libc::EDQUOT].contains(&result.exit_code) {
+            return true;
+        }
+        if !task.stderr.is_empty() && NsjailJudger::mentions_disk_full(&task.stderr, 0) {
+            return true;
+        }
+        match task.config.get("log") {
+            Some(log) => NsjailJudger::mentions_disk_full(log, log_start),
+            None => false,
+        }
+    }
+
+    fn execute(&mut self, cursor: usize, dir: &String) -> Result<JudgerResult, Error> {
+        let mut task = self.config.task_config[cursor].clone();
+        self.render_task(&mut task);
+        let (out_path, rep_path) = NsjailJudger::convert_task(dir, &mut task, &self.prefix, self.cursor);
+
+        let outputs = NsjailJudger::output_paths(&task);
+        let artifacts = outputs.iter()
+            .filter_map(|(key, path)| self.host_path(path).map(|x| (key.clone(), x)))
+            .collect::<HashMap<String, String>>();
+        // An output outside the mounted directories could not be restored,
+        // so such a task is never cached.
+        let key = match &self.cache {
+            Some(_) if !task.cache.is_empty() && artifacts.len() == outputs.len() => {
+                let rootfs_id = if self.config.rootfs_id.is_empty() {
+                    &self.config.rootfs_path
+                } else {
+                    &self.config.rootfs_id
+                };
+                CompileCache::key(&task.cache, &task, rootfs_id).ok()
+            },
+            _ => None,
+        };
+        if let (Some(cache), Some(key)) = (&self.cache, &key) {
+            if let Ok(true) = cache.restore(key, &artifacts, &out_path, &rep_path) {
+                let mut result = JudgerResult::from_file(&rep_path, &out_path)?;
+                result.files = self.collect_files(&task)?;
+                self.record_outputs(&task, &result);
+                return Ok(result);
+            }
+        }
+
+        // A missing or unreadable report counts as a failed attempt, so that
+        // it is retried like any other transient failure.
+        let mut result = JudgerResult::default();
+        for attempt in 0..=task.retry {
+            let _ = std::fs::remove_file(&rep_path);
+            let log_start = NsjailJudger::log_length(&task);
+            let usage = self.spawn(&task)?;
+            result = match JudgerResult::from_file(&rep_path, &out_path) {
+                Ok(x) => x,
+                Err(e) if attempt == task.retry => return Err(e),
+                Err(_) => continue,
+            };
+            result.disk_limit_exceeded = !task.accepts(&result) &&
+                (NsjailJudger::reports_disk_full(&task, log_start, &result) || usage.values().any(|x| x.is_full()));
+            result.disk_usage = usage;
+            result.files = self.collect_files(&task)?;
+            if task.accepts(&result) {
+                break;
+            }
+        }
+        if let (Some(cache), Some(key)) = (&self.cache, &key) {
+            if task.accepts(&result) {
+                let _ = cache.store(key, &artifacts, &out_path, &rep_path);
+            }
+        }
+        self.record_outputs(&task, &result);
+        Ok(result)
+    }
+
+    pub fn run(&mut self, cursor: i32) -> Result<JudgerResult, Error> {
+        let dir = match self.output_dir {
+            out if out < 0 => ".".to_string(),
+            out => self.dirs[out as usize].lock().unwrap().get_src(),
+        };
+        self.execute(cursor as usize, &dir)
+    }
+
+    pub fn run_step(&mut self) -> Result<JudgerResult, Error> {
         let result = self.run(self.cursor as i32);
         self.cursor += 1;
         result
     }
 
-    pub fn run_all(&mut self) -> Vec<JudgerResult> {
+    /// Runs the task called `name` alone, regardless of its `run_if`, and
+    /// tells whether it was accepted.
+    pub fn run_task(&mut self, name: &str) -> Result<(JudgerResult, bool), Error> {
+        let index = self.config.task_config.iter()
+            .position(|x| x.name == name)
+            .ok_or_else(|| Error::config(name, "no such task"))?;
+        self.cursor = index;
+        let result = self.run_step()?;
+        let accepted = self.config.task_config[index].accepts(&result);
+        Ok((result, accepted))
+    }
+
+    /// Runs a helper program of the problem, which has to be accepted.
+    pub fn run_accepted(&mut self, name: &str) -> Result<JudgerResult, Error> {
+        match self.run_task(name)? {
+            (result, true) => Ok(result),
+            (result, false) => Err(Error::task(name, &result)),
+        }
+    }
+
+    /// Runs the pipeline in order. A task is skipped when its `run_if`
+    /// condition does not match the state of the pipeline; once a task is
+    /// not accepted the pipeline is considered failed. The result of
+    /// `task_config[i]` is at index `i`, `None` if it was skipped.
+    ///
+    /// A system error fails the pipeline too: the `Always` and `OnFailure`
+    /// tasks after it still run, and the first error is returned at the end.
+    pub fn run_all(&mut self) -> Result<Vec<Option<JudgerResult>>, Error> {
         let mut ret = vec![];
+        let mut error = None;
         self.cursor = 0;
+        self.failed = false;
+        self.outputs.clear();
         while self.cursor < self.config.task_config.len() {
-            let tmp = self.run_step();
-            if tmp.exit_code != 0 || tmp.exit_signal != 0 {
-                ret.push(tmp);
-                break;
+            let task = &self.config.task_config[self.cursor];
+            let should_run = match task.run_if {
+                RunCondition::Always => true,
+                RunCondition::OnSuccess => !self.failed,
+                RunCondition::OnFailure => self.failed,
+            };
+            if !should_run {
+                self.cursor += 1;
+                ret.push(None);
+                continue;
             }
-            ret.push(tmp);
+            let index = self.cursor;
+            match self.run_step() {
+                Ok(tmp) => {
+                    if !self.config.task_config[index].accepts(&tmp) {
+                        self.failed = true;
+                    }
+                    ret.push(Some(tmp));
+                },
+                Err(e) => {
+                    self.failed = true;
+                    error.get_or_insert(e);
+                    ret.push(None);
+                },
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(ret),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir_utils::local::{Local, LocalConfig};
+    use crate::testing::scratch;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn exited(code: i32, signal: i32) -> JudgerResult {
+        JudgerResult { exit_code: code, exit_signal: signal, ..JudgerResult::default() }
+    }
+
+    #[test]
+    fn accepts_zero_exit_only() {
+        let task = NsjailTask::default();
+        assert!(task.accepts(&exited(0, 0)));
+        assert!(!task.accepts(&exited(1, 0)));
+        assert!(!task.accepts(&exited(0, 9)));
+    }
+
+    #[test]
+    fn allow_nonzero_still_rejects_signals() {
+        let task = NsjailTask { allow_nonzero: true, ..NsjailTask::default() };
+        assert!(task.accepts(&exited(3, 0)));
+        assert!(!task.accepts(&exited(0, 11)));
+    }
+
+    #[test]
+    fn task_fields_default_when_omitted() {
+        let task: NsjailTask = toml::from_str(r#"
+            config = {}
+            meta_data = {}
+            mount = []
+            exec = "/bin/true"
+            args = []
+            cwd = "/"
+            envs = {}
+        "#).unwrap();
+        assert_eq!(task.run_if, RunCondition::OnSuccess);
+        assert_eq!(task.retry, 0);
+        assert_eq!(task.collect_limit, default_collect_limit());
+        assert!(task.outputs.is_empty() && task.stdin.is_empty());
+
+        let task: NsjailTask = toml::from_str(r#"
+            config = {}
+            meta_data = {}
+            mount = []
+            exec = "/bin/true"
+            args = []
+            cwd = "/"
+            envs = {}
+            run_if = "on_failure"
+        "#).unwrap();
+        assert_eq!(task.run_if, RunCondition::OnFailure);
+    }
+
+    #[test]
+    fn mounts_resolve_inside_and_outside_the_sandbox() {
+        let mut jail = NsjailJudger::new(&NsjailConfig::default());
+        let dir = Local::from(&LocalConfig::new("/srv/data".to_string()));
+        jail.mount_all(Arc::new(Mutex::new(dir)), &"/test".to_string());
+        let context = jail.context();
+        assert_eq!(context["mount:/test"], "/test");
+        assert_eq!(context["host:/test"], "/srv/data");
+        assert_eq!(jail.host_path("/test/sub/a.out").as_deref(), Some("/srv/data/sub/a.out"));
+        assert_eq!(jail.host_path("/test").as_deref(), Some("/srv/data"));
+        assert_eq!(jail.host_path("/testing/a.out"), None);
+        assert_eq!(jail.host_path("/oj/a.out"), None);
+    }
+
+    #[test]
+    fn disk_full_is_reported_by_the_exit_status_or_the_logs() {
+        let tmp = scratch("disk_full");
+        let log = tmp.src.join("nsjail.log");
+        std::fs::write(&log, "[W] write(): No space left on device\n").unwrap();
+        let start = std::fs::metadata(&log).unwrap().len();
+        let task = NsjailTask {
+            config: HashMap::from([("log".to_string(), log.to_string_lossy().to_string())]),
+            ..NsjailTask::default()
+        };
+        assert_eq!(NsjailJudger::log_length(&task), start);
+        assert!(!NsjailJudger::reports_disk_full(&task, start, &exited(1, 0)));
+        assert!(NsjailJudger::reports_disk_full(&task, 0, &exited(1, 0)));
+        assert!(NsjailJudger::reports_disk_full(&task, start, &exited(libc::ENOSPC, 0)));
+        assert!(!NsjailJudger::reports_disk_full(&task, start, &exited(libc::EDQUOT, 9)));
+        std::fs::write(tmp.src.join("stderr"), "cp: Disk quota exceeded\n").unwrap();
+        let task = NsjailTask { stderr: tmp.src.join("stderr").to_string_lossy().to_string(), ..task };
+        assert!(NsjailJudger::reports_disk_full(&task, start, &exited(1, 0)));
+        let task = NsjailTask { stderr: tmp.src.join("none").to_string_lossy().to_string(), config: HashMap::new(), ..task };
+        assert!(!NsjailJudger::reports_disk_full(&task, 0, &exited(1, 0)));
+    }
+
+    /// A judger with `dir` mounted at `/test`, and a task in `/test`
+    /// collecting `patterns`.
+    fn collecting(dir: &Path, patterns: &[&str], collect_limit: u64) -> (NsjailJudger, NsjailTask) {
+        let mut jail = NsjailJudger::new(&NsjailConfig::default());
+        let local = Local::from(&LocalConfig::new(dir.join("mnt").to_string_lossy().to_string()));
+        jail.mount_all(Arc::new(Mutex::new(local)), &"/test".to_string());
+        let task = NsjailTask {
+            cwd: "/test".to_string(),
+            collect: patterns.iter().map(|x| x.to_string()).collect(),
+            collect_limit,
+            ..NsjailTask::default()
+        };
+        (jail, task)
+    }
+
+    fn collected(files: &[CollectedFile]) -> Vec<(&str, u64, &[u8], bool)> {
+        files.iter().map(|x| (x.path.as_str(), x.size, x.data.as_slice(), x.truncated)).collect()
+    }
+
+    #[test]
+    fn collect_files_matches_globs_in_order() {
+        let tmp = scratch("collect_glob");
+        std::fs::create_dir_all(tmp.src.join("mnt/sub")).unwrap();
+        for (name, data) in [("b.txt", "b"), ("a.txt", "a"), ("c.log", "c"), ("sub/d.txt", "d")] {
+            std::fs::write(tmp.src.join("mnt").join(name), data).unwrap();
         }
-        ret
+        let (jail, task) = collecting(&tmp.src, &["*.txt", "/test/sub/*"], default_collect_limit());
+        assert_eq!(collected(&jail.collect_files(&task).unwrap()), [
+            ("/test/a.txt", 1, &b"a"[..], false),
+            ("/test/b.txt", 1, &b"b"[..], false),
+            ("/test/sub/d.txt", 1, &b"d"[..], false),
+        ]);
+        let (jail, task) = collecting(&tmp.src, &["none.txt", "sub"], default_collect_limit());
+        assert!(jail.collect_files(&task).unwrap().is_empty());
+        let (jail, task) = collecting(&tmp.src, &["/etc/*"], default_collect_limit());
+        assert!(matches!(jail.collect_files(&task), Err(Error::Config { .. })));
+    }
+
+    #[test]
+    fn collect_limit_truncates_across_files() {
+        let tmp = scratch("collect_limit");
+        std::fs::create_dir_all(tmp.src.join("mnt")).unwrap();
+        for (name, data) in [("1.txt", "1234"), ("2.txt", "5678"), ("3.txt", "9")] {
+            std::fs::write(tmp.src.join("mnt").join(name), data).unwrap();
+        }
+        let (jail, task) = collecting(&tmp.src, &["*.txt"], 5);
+        assert_eq!(collected(&jail.collect_files(&task).unwrap()), [
+            ("/test/1.txt", 4, &b"1234"[..], false),
+            ("/test/2.txt", 4, &b"5"[..], true),
+            ("/test/3.txt", 1, &b""[..], true),
+        ]);
+    }
+
+    #[test]
+    fn collect_files_skips_symlinks_and_paths_outside_the_root() {
+        let tmp = scratch("collect_links");
+        std::fs::create_dir_all(tmp.src.join("mnt")).unwrap();
+        std::fs::create_dir_all(tmp.src.join("outside")).unwrap();
+        std::fs::write(tmp.src.join("mnt/a.txt"), "a").unwrap();
+        std::fs::write(tmp.src.join("outside/secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink("a.txt", tmp.src.join("mnt/link.txt")).unwrap();
+        std::os::unix::fs::symlink(tmp.src.join("outside/secret.txt"), tmp.src.join("mnt/secret.txt")).unwrap();
+        std::os::unix::fs::symlink(tmp.src.join("outside"), tmp.src.join("mnt/outside")).unwrap();
+        let (jail, task) = collecting(&tmp.src, &["*.txt", "outside/*", "../outside/*"], default_collect_limit());
+        let files = jail.collect_files(&task).unwrap();
+        assert_eq!(collected(&files), [("/test/a.txt", 1, &b"a"[..], false)]);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn unreadable_files_are_truncated_without_data() {
+        let tmp = scratch("collect_unreadable");
+        std::fs::create_dir_all(tmp.src.join("mnt")).unwrap();
+        std::fs::write(tmp.src.join("mnt/a.txt"), "abc").unwrap();
+        std::fs::set_permissions(tmp.src.join("mnt/a.txt"), std::fs::Permissions::from_mode(0o000)).unwrap();
+        let (jail, task) = collecting(&tmp.src, &["a.txt"], default_collect_limit());
+        let files = jail.collect_files(&task).unwrap();
+        // Root reads the file regardless of its mode.
+        if unsafe { libc::geteuid() } == 0 {
+            assert_eq!(collected(&files), [("/test/a.txt", 3, &b"abc"[..], false)]);
+        } else {
+            assert_eq!(collected(&files), [("/test/a.txt", 3, &b""[..], true)]);
+        }
+    }
+
+    #[test]
+    fn skipped_tasks_keep_their_slot() {
+        let task = NsjailTask { run_if: RunCondition::OnFailure, ..NsjailTask::default() };
+        let config = NsjailConfig {
+            task_config: vec![task.clone(), task],
+            ..NsjailConfig::default()
+        };
+        let mut jail = NsjailJudger::new(&config);
+        let result = jail.run_all().unwrap();
+        assert_eq!(result.len(), 2);
+        assert!(result.iter().all(|x| x.is_none()));
+    }
+}
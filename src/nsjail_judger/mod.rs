@@ -1,3 +1,10 @@
 pub mod judger;
 pub mod traits;
-pub mod result;
\ No newline at end of file
+pub mod result;
+pub mod template;
+
+pub mod cache;
+pub mod stress;
+pub mod hack;
+pub mod validate;
+pub mod answer;
\ No newline at end of file
@@ -1,4 +1,15 @@
+use std::collections::HashMap;
 use std::fs::read_to_string;
+use crate::dir_utils::tmpfs::DiskUsage;
+use crate::error::Error;
+
+#[derive(Clone, Debug)]
+pub struct CollectedFile {
+    pub path: String,
+    pub size: u64,
+    pub data: Vec<u8>,
+    pub truncated: bool,
+}
 
 #[derive(Clone, Debug)]
 pub struct JudgerResult {
@@ -7,7 +18,10 @@ pub struct JudgerResult {
     pub exit_code: i32,
     pub exit_signal: i32,
     pub report_path: String,
-    pub output_path: String
+    pub output_path: String,
+    pub disk_usage: HashMap<String, DiskUsage>,
+    pub disk_limit_exceeded: bool,
+    pub files: Vec<CollectedFile>,
 }
 
 impl Default for JudgerResult {
@@ -18,33 +32,80 @@ impl Default for JudgerResult {
             exit_code: -1,
             exit_signal: -1,
             report_path: "".to_string(),
-            output_path: "".to_string()
+            output_path: "".to_string(),
+            disk_usage: HashMap::new(),
+            disk_limit_exceeded: false,
+            files: vec![],
         }
     }
 }
 
 impl JudgerResult {
-    pub fn from_file(path: &String, output: &String) -> Self {
-        let report = read_to_string(path);
-        match report {
-            Ok(report) => {
-                let lines = report
-                    .split_ascii_whitespace()
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>();
-                if lines.len() >= 15 {
-                    return Self {
-                        memory: lines[2].parse::<i32>().unwrap_or(-1),
-                        time: lines[8].parse::<f32>().unwrap_or(-1.0),
-                        exit_code: lines[11].parse::<i32>().unwrap_or(-1),
-                        exit_signal: lines[14].parse::<i32>().unwrap_or(-1),
-                        report_path: path.clone(),
-                        output_path: output.clone(),
-                    };
-                }
-                Self::default()
-            }
-            Err(_) => Self::default()
+    pub fn from_file(path: &String, output: &String) -> Result<Self, Error> {
+        let report = read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let lines = report
+            .split_ascii_whitespace()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+        if lines.len() < 15 {
+            return Err(Error::ReportParse {
+                path: path.into(),
+                reason: format!("expected at least 15 fields, found {}", lines.len()),
+            });
         }
+        let field = |index: usize| Error::ReportParse {
+            path: path.into(),
+            reason: format!("invalid field {}: {}", index, lines[index]),
+        };
+        Ok(Self {
+            memory: lines[2].parse::<i32>().map_err(|_| field(2))?,
+            time: lines[8].parse::<f32>().map_err(|_| field(8))?,
+            exit_code: lines[11].parse::<i32>().map_err(|_| field(11))?,
+            exit_signal: lines[14].parse::<i32>().map_err(|_| field(14))?,
+            report_path: path.clone(),
+            output_path: output.clone(),
+            ..Self::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch;
+    use std::path::Path;
+
+    fn report(dir: &Path, name: &str, content: &str) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parses_the_report_fields() {
+        let tmp = scratch("report_valid");
+        let path = report(&tmp.src, "rep", "a b 2048 c d e f g 0.25 h i 3 j k 0\n");
+        let result = JudgerResult::from_file(&path, &"out".to_string()).unwrap();
+        assert_eq!((result.memory, result.exit_code, result.exit_signal), (2048, 3, 0));
+        assert_eq!(result.time, 0.25);
+        assert_eq!(result.output_path, "out");
+    }
+
+    #[test]
+    fn short_or_invalid_reports_are_errors() {
+        let tmp = scratch("report_invalid");
+        let path = report(&tmp.src, "short", "1 2 3");
+        assert!(matches!(JudgerResult::from_file(&path, &"out".to_string()), Err(Error::ReportParse { .. })));
+        let path = report(&tmp.src, "invalid", "a b many c d e f g 0.25 h i 3 j k 0");
+        match JudgerResult::from_file(&path, &"out".to_string()) {
+            Err(Error::ReportParse { reason, .. }) => assert_eq!(reason, "invalid field 2: many"),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn missing_report_is_not_found() {
+        let path = "/nonexistent/jcoder.rep".to_string();
+        assert!(matches!(JudgerResult::from_file(&path, &"out".to_string()), Err(Error::NotFound { .. })));
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs;
+use crate::compare::compare::{judge_file, CompareConfig};
+use crate::compare::results::{BaseStatus, CompareResult};
+use crate::error::Error;
+use crate::nsjail_judger::judger::NsjailJudger;
+use crate::nsjail_judger::result::JudgerResult;
+
+/// Runs the `generator` task with `{seed}` set, then the `reference` and
+/// `submission` tasks, which read the generated input through their `stdin`.
+#[derive(Clone)]
+pub struct StressConfig {
+    pub generator: String,
+    pub reference: String,
+    pub submission: String,
+    /// The first seed; every run uses the next one, wrapping around after
+    /// `u64::MAX`.
+    pub seed: u64,
+    pub iterations: u64,
+    pub compare: CompareConfig,
+    /// Where the input of the first failing run is saved.
+    pub input_path: String,
+}
+
+impl StressConfig {
+    fn seed_of(&self, run: u64) -> u64 {
+        self.seed.wrapping_add(run)
+    }
+}
+
+pub struct StressFailure {
+    pub seed: u64,
+    pub input_path: String,
+    pub submission: JudgerResult,
+    /// The comparison, if the submission exited cleanly.
+    pub result: Option<CompareResult>,
+}
+
+pub struct StressReport {
+    pub runs: u64,
+    pub failure: Option<StressFailure>,
+}
+
+/// Runs the submission against the reference on generated inputs until
+/// they disagree or `iterations` runs pass. The submission fails when it
+/// does not exit cleanly or its output is not accepted; a failing generator
+/// or reference is an error.
+pub fn stress(jail: &mut NsjailJudger, config: &StressConfig) -> Result<StressReport, Error> {
+    for run in 0..config.iterations {
+        let seed = config.seed_of(run);
+        jail.set_var("seed", &seed.to_string());
+        let input = jail.run_accepted(&config.generator)?;
+        let answer = jail.run_accepted(&config.reference)?;
+        let (submission, accepted) = jail.run_task(&config.submission)?;
+        let result = match accepted {
+            true => Some(judge_file(&answer.output_path, &submission.output_path, &config.compare)?),
+            false => None,
+        };
+        if result.as_ref().map(|x| x.status == BaseStatus::AC).unwrap_or(false) {
+            continue;
+        }
+        fs::copy(&input.output_path, &config.input_path).map_err(|e| Error::io(&config.input_path, e))?;
+        return Ok(StressReport {
+            runs: run + 1,
+            failure: Some(StressFailure { seed, input_path: config.input_path.clone(), submission, result }),
+        });
+    }
+    Ok(StressReport { runs: config.iterations, failure: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nsjail_judger::judger::NsjailConfig;
+
+    #[test]
+    fn no_iterations_runs_nothing() {
+        let mut jail = NsjailJudger::new(&NsjailConfig::default());
+        let report = stress(&mut jail, &StressConfig { iterations: 0, ..StressConfig::default() }).unwrap();
+        assert_eq!(report.runs, 0);
+        assert!(report.failure.is_none());
+    }
+
+    #[test]
+    fn missing_generator_is_a_config_error() {
+        let mut jail = NsjailJudger::new(&NsjailConfig::default());
+        let config = StressConfig { seed: 7, input_path: "/nonexistent/stress.in".to_string(), ..StressConfig::default() };
+        assert!(matches!(stress(&mut jail, &config), Err(Error::Config { path, .. }) if path.as_os_str() == "generator"));
+        assert_eq!(jail.context()["seed"], "7");
+    }
+
+    #[test]
+    fn seeds_wrap_around() {
+        let config = StressConfig { seed: u64::MAX, iterations: 3, ..StressConfig::default() };
+        assert_eq!([0, 1, 2].map(|x| config.seed_of(x)), [u64::MAX, 0, 1]);
+        let mut jail = NsjailJudger::new(&NsjailConfig::default());
+        assert!(matches!(stress(&mut jail, &config), Err(Error::Config { .. })));
+        assert_eq!(jail.context()["seed"], u64::MAX.to_string());
+    }
+}
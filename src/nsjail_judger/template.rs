@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+/// Substitutes `{name}` placeholders in `template` with values from `vars`.
+/// `{{` and `}}` produce literal braces; unknown placeholders are left as is
+/// so that arguments such as `find -exec {} ;` pass through untouched.
+pub fn render(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            result.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        if rest.starts_with('}') {
+            result.push('}');
+            rest = &rest[1..];
+            continue;
+        }
+        match rest.find('}') {
+            Some(end) => {
+                match vars.get(&rest[1..end]) {
+                    Some(value) => result.push_str(value),
+                    None => result.push_str(&rest[..=end]),
+                }
+                rest = &rest[end + 1..];
+            }
+            None => break,
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> HashMap<String, String> {
+        HashMap::from([
+            ("source".to_string(), "main.cpp".to_string()),
+            ("compile.binary".to_string(), "/test/a.out".to_string()),
+            ("braced".to_string(), "{source}".to_string()),
+        ])
+    }
+
+    #[test]
+    fn substitutes_known_names() {
+        assert_eq!(render("g++ {source} -o {compile.binary}", &vars()), "g++ main.cpp -o /test/a.out");
+        assert_eq!(render("{source}{source}", &vars()), "main.cppmain.cpp");
+    }
+
+    #[test]
+    fn keeps_unknown_and_empty_placeholders() {
+        assert_eq!(render("find -exec {} ;", &vars()), "find -exec {} ;");
+        assert_eq!(render("{missing}", &vars()), "{missing}");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(render("{{source}}", &vars()), "{source}");
+        assert_eq!(render("a}}b{{c", &vars()), "a}b{c");
+        assert_eq!(render("lone } brace", &vars()), "lone } brace");
+    }
+
+    #[test]
+    fn unterminated_placeholder_is_kept() {
+        assert_eq!(render("x {source", &vars()), "x {source");
+    }
+
+    #[test]
+    fn values_are_not_rendered_again() {
+        assert_eq!(render("{braced}", &vars()), "{source}");
+    }
+}
@@ -1,5 +1,11 @@
 use std::collections::HashMap;
-use crate::nsjail_judger::judger::NsjailTask;
+use crate::nsjail_judger::cache::{CacheConfig, DEFAULT_CACHE_PATH};
+use crate::nsjail_judger::judger::{default_collect_limit, NsjailTask, RunCondition};
+use crate::nsjail_judger::result::JudgerResult;
+use crate::nsjail_judger::stress::StressConfig;
+use crate::nsjail_judger::hack::HackConfig;
+use crate::nsjail_judger::answer::AnswerConfig;
+use crate::compare::compare::CompareConfig;
 
 impl NsjailTask {
     pub fn to_args(&self, rootfs: &String) -> Vec<String> {
@@ -34,6 +40,15 @@ impl NsjailTask {
         }
         result
     }
+
+    /// A run is accepted when it was not killed by a signal and exited with
+    /// zero, or with any code if `allow_nonzero` is set.
+    pub fn accepts(&self, result: &JudgerResult) -> bool {
+        if result.exit_signal != 0 {
+            return false;
+        }
+        result.exit_code == 0 || (self.allow_nonzero && result.exit_code > 0)
+    }
 }
 
 impl Default for NsjailTask {
@@ -46,6 +61,78 @@ impl Default for NsjailTask {
             args: vec![],
             cwd: "/".to_string(),
             envs: HashMap::from([("PATH".to_string(), "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string())]),
+            name: "".to_string(),
+            run_if: RunCondition::OnSuccess,
+            allow_nonzero: false,
+            retry: 0,
+            outputs: HashMap::new(),
+            cache: vec![],
+            collect: vec![],
+            collect_limit: default_collect_limit(),
+            stdin: "".to_string(),
+            stderr: "".to_string(),
+        }
+    }
+}
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            path: "".to_string(),
+            max_size: 1 << 30,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        Self {
+            generator: "generator".to_string(),
+            reference: "reference".to_string(),
+            submission: "submission".to_string(),
+            seed: 1,
+            iterations: 1000,
+            compare: CompareConfig::default(),
+            input_path: "stress.in".to_string(),
+        }
+    }
+}
+
+impl Default for HackConfig {
+    fn default() -> Self {
+        Self {
+            validator: "validator".to_string(),
+            reference: "reference".to_string(),
+            target: "target".to_string(),
+            input_path: "".to_string(),
+            compare: CompareConfig::default(),
+        }
+    }
+}
+
+impl Default for AnswerConfig {
+    fn default() -> Self {
+        Self {
+            compile: "compile".to_string(),
+            reference: "reference".to_string(),
+            validator: None,
+            limits: HashMap::from([
+                ("time_limit".to_string(), "60".to_string()),
+                ("rlimit_as".to_string(), "inf".to_string()),
+                ("cgroup_mem_max".to_string(), "0".to_string()),
+                ("cgroup_mem_memsw_max".to_string(), "0".to_string()),
+                ("cgroup_mem_swap_max".to_string(), "-1".to_string()),
+            ]),
+            extension: "txt".to_string(),
+            cache: Some(CacheConfig::new(DEFAULT_CACHE_PATH.to_string())),
         }
     }
-}
\ No newline at end of file
+}
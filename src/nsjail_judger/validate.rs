@@ -0,0 +1,122 @@
+use std::fs;
+use crate::error::Error;
+use crate::nsjail_judger::judger::NsjailJudger;
+
+/// A test input and the subtask it belongs to.
+#[derive(Clone, Debug)]
+pub struct TestInput {
+    pub path: String,
+    pub subtask: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ValidationFailure {
+    pub input: TestInput,
+    pub exit_code: i32,
+    pub exit_signal: i32,
+    /// What the validator wrote to its `stderr` file, or to the standard
+    /// output when the task has none.
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    pub checked: usize,
+    pub failures: Vec<ValidationFailure>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Lists the `.in` files of a test directory, sorted by name. Files in a
+/// subdirectory belong to the subtask named after it.
+pub fn test_inputs(path: &str) -> Result<Vec<TestInput>, Error> {
+    let mut result = vec![];
+    let mut entries = fs::read_dir(path).map_err(|e| Error::io(path, e))?
+        .map(|x| x.map(|x| x.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::io(path, e))?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            let subtask = entry.file_name().map(|x| x.to_string_lossy().to_string());
+            for input in test_inputs(&entry.to_string_lossy())? {
+                result.push(TestInput { subtask: input.subtask.or(subtask.clone()), ..input });
+            }
+        } else if entry.extension().map(|x| x == "in").unwrap_or(false) {
+            result.push(TestInput { path: entry.to_string_lossy().to_string(), subtask: None });
+        }
+    }
+    Ok(result)
+}
+
+/// Runs the validator task on every input with `{input}` and `{subtask}`
+/// set; `{subtask}` is empty for inputs outside a subtask. An input is
+/// rejected when the validator is not accepted.
+pub fn validate(jail: &mut NsjailJudger, validator: &str, inputs: &[TestInput]) -> Result<ValidationReport, Error> {
+    let mut report = ValidationReport::default();
+    for input in inputs {
+        jail.set_var("input", &input.path);
+        jail.set_var("subtask", input.subtask.as_deref().unwrap_or(""));
+        let (result, accepted) = jail.run_task(validator)?;
+        report.checked += 1;
+        if accepted {
+            continue;
+        }
+        let messages = jail.outputs.get(&format!("host:{}.stderr", validator)).unwrap_or(&result.output_path);
+        let message = fs::read(messages)
+            .map(|x| String::from_utf8_lossy(&x).trim().to_string())
+            .unwrap_or_default();
+        report.failures.push(ValidationFailure {
+            input: input.clone(),
+            exit_code: result.exit_code,
+            exit_signal: result.exit_signal,
+            message,
+        });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nsjail_judger::judger::NsjailConfig;
+    use crate::testing::scratch;
+
+    #[test]
+    fn inputs_are_listed_with_their_subtask() {
+        let tmp = scratch("inputs");
+        let path = &tmp.src;
+        for dir in ["a", "a/b", "c"] {
+            fs::create_dir_all(path.join(dir)).unwrap();
+        }
+        for file in ["2.in", "10.in", "1.ans", "a/1.in", "a/b/1.in", "c/notes.txt", "a.in.bak"] {
+            fs::write(path.join(file), b"").unwrap();
+        }
+        let inputs = test_inputs(&path.to_string_lossy()).unwrap().into_iter()
+            .map(|x| (x.path.strip_prefix(&*path.to_string_lossy()).unwrap().to_string(), x.subtask))
+            .collect::<Vec<_>>();
+        let subtask = |x: &str| Some(x.to_string());
+        assert_eq!(inputs, [
+            ("/10.in".to_string(), None),
+            ("/2.in".to_string(), None),
+            ("/a/1.in".to_string(), subtask("a")),
+            ("/a/b/1.in".to_string(), subtask("b")),
+        ]);
+        assert!(matches!(test_inputs(&path.join("none").to_string_lossy()), Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn validator_task_is_only_needed_when_there_are_inputs() {
+        let mut jail = NsjailJudger::new(&NsjailConfig::default());
+        let report = validate(&mut jail, "validator", &[]).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.checked, 0);
+        let input = TestInput { path: "1.in".to_string(), subtask: None };
+        assert!(matches!(validate(&mut jail, "validator", &[input]), Err(Error::Config { .. })));
+        assert_eq!(jail.context()["subtask"], "");
+    }
+}
@@ -0,0 +1,18 @@
+use crate::dir_utils::guard::DirGuard;
+use crate::dir_utils::local::{Local, LocalConfig};
+use crate::dir_utils::traits::BaseDir;
+
+/// Creates an empty scratch directory for a unit test. The directory is
+/// removed when the guard is dropped, also when the test fails.
+pub fn scratch(name: &str) -> DirGuard<Local> {
+    let path = std::env::temp_dir().join(format!("jcoder_test_{}_{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&path);
+    let mut dir = DirGuard::new(Local::from(&LocalConfig {
+        create_if_not_exist: true,
+        clean: true,
+        read_only: false,
+        ..LocalConfig::new(path.to_string_lossy().to_string())
+    }));
+    dir.create().unwrap();
+    dir
+}